@@ -0,0 +1,238 @@
+use serde::{Serialize, Serializer};
+
+use crate::{Error, Result};
+
+/// `AudioFormat` of `SpeechTranslate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioFormat {
+    /// raw pcm, 16kHz mono 16 bit little endian, wav input is converted
+    Pcm,
+    Speex,
+    Mp3,
+}
+
+impl AudioFormat {
+    /// value sent as `AudioFormat`, as listed by the `SpeechTranslate` api
+    /// reference. Older examples quote 83886080 for pcm and 33554432 for
+    /// speex, the reference has 83886080 for mp3 and no 33554432.
+    pub fn code(&self) -> u32 {
+        match self {
+            AudioFormat::Pcm => 146,
+            AudioFormat::Speex => 16779154,
+            AudioFormat::Mp3 => 83886080,
+        }
+    }
+
+    /// bytes in a fragment of at most 200ms, the longest the api accepts
+    pub(crate) fn frame_size(&self) -> usize {
+        match self {
+            // 16000 samples/s * 2 bytes * 0.2s
+            AudioFormat::Pcm => 6400,
+            // 200ms at 48kbps, compressed frames can not be measured exactly
+            AudioFormat::Speex | AudioFormat::Mp3 => 1200,
+        }
+    }
+}
+
+impl TryFrom<u32> for AudioFormat {
+    type Error = Error;
+
+    fn try_from(code: u32) -> Result<Self> {
+        match code {
+            146 => Ok(AudioFormat::Pcm),
+            16779154 => Ok(AudioFormat::Speex),
+            83886080 => Ok(AudioFormat::Mp3),
+            _ => Err(Error::InvalidArgument(format!(
+                "unknown audio format: {code}"
+            ))),
+        }
+    }
+}
+
+impl Serialize for AudioFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.code())
+    }
+}
+
+/// Layout of pcm samples, as found in a wav `fmt ` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+impl WavSpec {
+    /// what [`AudioFormat::Pcm`] requires
+    pub const SPEECH: WavSpec = WavSpec {
+        sample_rate: 16000,
+        channels: 1,
+        bits_per_sample: 16,
+    };
+}
+
+pub(crate) enum WavHeader {
+    /// not a wav file, taken as raw samples
+    NotWav,
+    /// more bytes are needed to tell
+    Incomplete,
+    /// samples start at `offset` and run for `len` bytes when known
+    Wav {
+        spec: WavSpec,
+        offset: usize,
+        len: Option<usize>,
+    },
+}
+
+/// Parse the header of a (possibly partial) wav file
+pub(crate) fn parse_wav_header(data: &[u8]) -> Result<WavHeader> {
+    if data.len() < 12 {
+        let riff = &b"RIFF"[..data.len().min(4)];
+        return Ok(if data.starts_with(riff) {
+            WavHeader::Incomplete
+        } else {
+            WavHeader::NotWav
+        });
+    }
+    if &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Ok(WavHeader::NotWav);
+    }
+
+    let invalid = |message: &str| Error::InvalidArgument(format!("invalid wav: {message}"));
+    let mut spec = None;
+    let mut pos = 12;
+    loop {
+        let Some(header) = data.get(pos..pos + 8) else {
+            return Ok(WavHeader::Incomplete);
+        };
+        let id = &header[..4];
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let body = pos + 8;
+        match id {
+            b"fmt " => {
+                let Some(fmt) = data.get(body..body + 16) else {
+                    return Ok(WavHeader::Incomplete);
+                };
+                let tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                // 1 is integer pcm, 0xfffe is the extensible header
+                if tag != 1 && tag != 0xfffe {
+                    return Err(invalid(
+                        "samples are compressed, only integer pcm is supported",
+                    ));
+                }
+                spec = Some(WavSpec {
+                    channels: u16::from_le_bytes([fmt[2], fmt[3]]),
+                    sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                    bits_per_sample: u16::from_le_bytes([fmt[14], fmt[15]]),
+                });
+            }
+            b"data" => {
+                let spec = spec.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                // streamed wav files leave the size at 0 or u32::MAX
+                let len = (size != 0 && size != u32::MAX as usize).then_some(size);
+                return Ok(WavHeader::Wav {
+                    spec,
+                    offset: body,
+                    len,
+                });
+            }
+            _ => {}
+        }
+        // chunks are padded to an even size
+        pos = body + size + (size & 1);
+    }
+}
+
+/// Check `spec` against what the api accepts for `AudioFormat::Pcm`
+pub(crate) fn check_wav_spec(spec: &WavSpec) -> Result<()> {
+    if *spec == WavSpec::SPEECH {
+        return Ok(());
+    }
+    Err(Error::InvalidArgument(format!(
+        "wav must be 16000Hz mono 16 bit, got {}Hz {} channel(s) {} bit",
+        spec.sample_rate, spec.channels, spec.bits_per_sample
+    )))
+}
+
+/// Raw pcm samples of a complete wav file, after checking its format. Data
+/// which does not start with the whole `RIFF....WAVE` magic, e.g. a short
+/// last fragment of raw samples, is returned unchanged.
+pub fn wav_to_pcm(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < 12 {
+        return Ok(data);
+    }
+    match parse_wav_header(data)? {
+        WavHeader::NotWav => Ok(data),
+        WavHeader::Incomplete => Err(Error::InvalidArgument(
+            "invalid wav: truncated header".to_string(),
+        )),
+        WavHeader::Wav { spec, offset, len } => {
+            check_wav_spec(&spec)?;
+            let end = len.map_or(data.len(), |len| (offset + len).min(data.len()));
+            Ok(&data[offset..end])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(spec: WavSpec, samples: &[u8]) -> Vec<u8> {
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+        data.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        data.extend_from_slice(b"fmt \x10\0\0\0\x01\0");
+        data.extend_from_slice(&spec.channels.to_le_bytes());
+        data.extend_from_slice(&spec.sample_rate.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&spec.bits_per_sample.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        data.extend_from_slice(samples);
+        data.extend_from_slice(b"tail");
+        data
+    }
+
+    #[test]
+    fn wav_is_converted_to_pcm() {
+        let data = wav(WavSpec::SPEECH, b"\x01\x02\x03\x04");
+        assert_eq!(wav_to_pcm(&data).unwrap(), b"\x01\x02\x03\x04");
+        assert_eq!(wav_to_pcm(b"raw samples").unwrap(), b"raw samples");
+        // too short to be wav, even when it starts like one
+        assert_eq!(wav_to_pcm(b"RIFF\x01\x02").unwrap(), b"RIFF\x01\x02");
+        assert_eq!(wav_to_pcm(b"").unwrap(), b"");
+        assert!(matches!(
+            wav_to_pcm(&data[..20]),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            parse_wav_header(&data[..20]).unwrap(),
+            WavHeader::Incomplete
+        ));
+
+        let stereo = WavSpec {
+            channels: 2,
+            ..WavSpec::SPEECH
+        };
+        assert!(matches!(
+            wav_to_pcm(&wav(stereo, b"\0\0\0\0")),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn audio_format_codes() {
+        assert_eq!(AudioFormat::try_from(146).unwrap(), AudioFormat::Pcm);
+        assert_eq!(AudioFormat::Mp3.code(), 83886080);
+        assert_eq!(
+            serde_json::to_string(&AudioFormat::Speex).unwrap(),
+            "16779154"
+        );
+        // the documented codes, not the ones of older examples
+        for format in [AudioFormat::Pcm, AudioFormat::Speex, AudioFormat::Mp3] {
+            assert_eq!(AudioFormat::try_from(format.code()).unwrap(), format);
+        }
+        assert!(AudioFormat::try_from(33554432).is_err());
+    }
+}
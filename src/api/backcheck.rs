@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{decode_response, Language, TextTranslateResponse, TranslateKind, TranslateMethods};
+use crate::{client::Delegate, Result, TencentClient};
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate texts, translate them back and score the round trip
+    pub fn back_translate(&self) -> BackTranslateCallBuilder<'a, S> {
+        BackTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Translate every text from `source` to `target` and back with
+/// `TextTranslate`, then compare the round trip with the original.
+///
+/// The score is a character n-gram F-score between 0 and 1, see
+/// [`similarity`]. Items scoring below `threshold` are flagged, as are items
+/// detected in a language they can not be translated back from.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct BackTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// abort the round trips still to come once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    /// with `auto` the detected language is used for the way back, text
+    /// detected as `target` is not translated back and scores 1
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    #[builder(setter(into))]
+    texts: Vec<String>,
+    #[builder(default = "0.5")]
+    threshold: f64,
+}
+
+impl<'a, S> BackTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        if let Some(threshold) = self.threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(format!("threshold {threshold} is not within 0..=1"));
+            }
+        }
+        TranslateKind::Text.validate(self.source, self.target)?;
+        match self.source {
+            Some(Language::Auto) | None => Ok(()),
+            source => TranslateKind::Text.validate(self.target, source),
+        }
+    }
+}
+
+/// One text checked by [`BackTranslateCall`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackTranslation {
+    pub original: String,
+    pub translated: String,
+    pub round_trip: String,
+    /// similarity of `original` and `round_trip`, 1 is identical
+    pub score: f64,
+    /// `score` is below the threshold, or the text could not be checked
+    pub flagged: bool,
+    /// why there was no way back, `round_trip` is empty then
+    pub unchecked: Option<String>,
+}
+
+impl<'a, S> BackTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// returns a result per text, in order
+    pub async fn doit(mut self) -> Result<Vec<BackTranslation>> {
+        let mut result = Vec::with_capacity(self.texts.len());
+        for original in std::mem::take(&mut self.texts) {
+            if original.trim().is_empty() {
+                result.push(BackTranslation {
+                    translated: original.clone(),
+                    round_trip: original.clone(),
+                    original,
+                    score: 1.0,
+                    flagged: false,
+                    unchecked: None,
+                });
+                continue;
+            }
+            let there = self.translate(self.source, self.target, &original).await?;
+            let back = match there.source_language() {
+                // detected as already in target, there is nothing to go back to
+                Some(source) if source == self.target => {
+                    result.push(BackTranslation {
+                        translated: there.target_text,
+                        round_trip: original.clone(),
+                        original,
+                        score: 1.0,
+                        flagged: false,
+                        unchecked: None,
+                    });
+                    continue;
+                }
+                Some(source) => TranslateKind::Text
+                    .validate(Some(self.target), Some(source))
+                    .map(|_| source),
+                None => Err(format!("unsupported source language {}", there.source)),
+            };
+            // one odd text does not cost the others their check
+            let source = match back {
+                Ok(source) => source,
+                Err(reason) => {
+                    result.push(BackTranslation {
+                        original,
+                        translated: there.target_text,
+                        round_trip: String::new(),
+                        score: 0.0,
+                        flagged: true,
+                        unchecked: Some(reason),
+                    });
+                    continue;
+                }
+            };
+            let back = self
+                .translate(self.target, source, &there.target_text)
+                .await?;
+            let score = similarity(&original, &back.target_text);
+            result.push(BackTranslation {
+                original,
+                translated: there.target_text,
+                round_trip: back.target_text,
+                score,
+                flagged: score < self.threshold,
+                unchecked: None,
+            });
+        }
+        Ok(result)
+    }
+
+    async fn translate(
+        &mut self,
+        source: Language,
+        target: Language,
+        text: &str,
+    ) -> Result<TextTranslateResponse> {
+        let mut builder = self
+            .client
+            .translate()
+            .text_translate()
+            .project_id(self.project_id)
+            .source(source)
+            .target(target)
+            .region(self.region.as_str())
+            .source_text(text);
+        if let Some(dlg) = self.delegate.as_deref_mut() {
+            builder = builder.delegate(dlg);
+        }
+        if let Some(ref token) = self.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        decode_response(&body)
+    }
+}
+
+/// Character n-gram F-score (chrF, n = 1..=3) of two texts, between 0 and 1.
+///
+/// Case and whitespace are ignored, so it works the same for languages with
+/// and without spaces between words.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    const MAX_N: usize = 3;
+
+    let normalize = |s: &str| -> Vec<char> {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+
+    let mut total = 0.0;
+    let mut orders = 0;
+    for n in 1..=MAX_N {
+        if a.len() < n || b.len() < n {
+            break;
+        }
+        let (grams_a, grams_b) = (ngrams(&a, n), ngrams(&b, n));
+        let common: usize = grams_a
+            .iter()
+            .map(|(gram, &count)| count.min(grams_b.get(gram).copied().unwrap_or(0)))
+            .sum();
+        let precision = common as f64 / (b.len() - n + 1) as f64;
+        let recall = common as f64 / (a.len() - n + 1) as f64;
+        if precision + recall > 0.0 {
+            total += 2.0 * precision * recall / (precision + recall);
+        }
+        orders += 1;
+    }
+    total / orders as f64
+}
+
+fn ngrams(chars: &[char], n: usize) -> HashMap<&[char], usize> {
+    let mut grams = HashMap::new();
+    for gram in chars.windows(n) {
+        *grams.entry(gram).or_insert(0) += 1;
+    }
+    grams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock;
+
+    #[tokio::test]
+    async fn round_trip_with_detected_source() {
+        let (client, seen) = mock::sequence(vec![
+            r#"{"Response":{"RequestId":"1","Source":"en","Target":"zh","TargetText":"你好"}}"#,
+            r#"{"Response":{"RequestId":"2","Source":"zh","Target":"en","TargetText":"Hello"}}"#,
+            // already in target
+            r#"{"Response":{"RequestId":"3","Source":"zh","Target":"zh","TargetText":"你好"}}"#,
+        ]);
+        let results = client
+            .translate()
+            .back_translate()
+            .project_id(0u32)
+            .source(Language::Auto)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .texts(vec!["hello".to_string(), "你好".to_string()])
+            .build()
+            .unwrap()
+            .doit()
+            .await
+            .unwrap();
+        assert_eq!(results[0].round_trip, "Hello");
+        assert_eq!(results[0].score, 1.0);
+        assert_eq!(results[1].round_trip, "你好");
+        assert_eq!(results[1].score, 1.0);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(seen[1].1.contains(r#""Target":"en""#), "{}", seen[1].1);
+    }
+
+    #[tokio::test]
+    async fn texts_without_a_way_back_are_flagged() {
+        let (client, seen) = mock::sequence(vec![
+            // a source the crate does not know
+            r#"{"Response":{"RequestId":"1","Source":"xx","Target":"zh","TargetText":"你好"}}"#,
+            r#"{"Response":{"RequestId":"2","Source":"en","Target":"zh","TargetText":"世界"}}"#,
+            r#"{"Response":{"RequestId":"3","Source":"zh","Target":"en","TargetText":"world"}}"#,
+        ]);
+        let results = client
+            .translate()
+            .back_translate()
+            .project_id(0u32)
+            .source(Language::Auto)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .texts(vec!["hello".to_string(), "world".to_string()])
+            .build()
+            .unwrap()
+            .doit()
+            .await
+            .unwrap();
+        assert!(results[0].flagged);
+        assert!(results[0].unchecked.as_ref().unwrap().contains("xx"));
+        assert_eq!(results[0].translated, "你好");
+        assert_eq!(results[1].round_trip, "world");
+        assert!(!results[1].flagged && results[1].unchecked.is_none());
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn similarity_scores() {
+        assert_eq!(similarity("Hello World", "hello  world"), 1.0);
+        assert_eq!(similarity("abc", "xyz"), 0.0);
+        assert_eq!(similarity("", ""), 1.0);
+
+        let close = similarity("the cat sat on the mat", "the cat sits on the mat");
+        let far = similarity("the cat sat on the mat", "a dog ran in the park");
+        assert!(close > 0.8, "{close}");
+        assert!(far < 0.5, "{far}");
+        assert!(similarity("我喜欢苹果", "我爱吃苹果") > similarity("我喜欢苹果", "他在看书"));
+    }
+}
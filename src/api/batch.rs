@@ -0,0 +1,87 @@
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{decode_response, Language, TextTranslateBatchResponse};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+/// `TextTranslateBatch` accepts no more than this many characters per request
+const MAX_BATCH_CHARS: usize = 2000;
+
+pub(crate) struct BatchArg<'a> {
+    pub project_id: u32,
+    pub source: Language,
+    pub target: Language,
+    pub region: &'a str,
+    pub cancel: Option<&'a CancellationToken>,
+}
+
+/// Translate `texts` with as few `TextTranslateBatch` requests as possible,
+/// blank texts are never sent and come back unchanged. The result keeps the
+/// order of `texts`.
+pub(crate) async fn translate_texts<S>(
+    client: &TencentClient<S>,
+    mut delegate: Option<&mut dyn Delegate>,
+    arg: &BatchArg<'_>,
+    texts: Vec<String>,
+) -> Result<Vec<String>>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut result = texts.clone();
+    for chunk in chunks(&texts) {
+        let list = chunk.iter().map(|&i| texts[i].clone()).collect::<Vec<_>>();
+        let mut builder = client
+            .translate()
+            .text_batch_translate()
+            .project_id(arg.project_id)
+            .source(arg.source)
+            .target(arg.target)
+            .region(arg.region)
+            .source_text_list(list);
+        if let Some(dlg) = delegate.as_deref_mut() {
+            builder = builder.delegate(dlg);
+        }
+        if let Some(token) = arg.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        let response: TextTranslateBatchResponse = decode_response(&body)?;
+        if response.target_text_list.len() != chunk.len() {
+            return Err(Error::BadRequest(serde_json::json!({
+                "RequestId": response.request_id,
+                "Message": "TargetTextList does not match SourceTextList",
+            })));
+        }
+        for (i, text) in chunk.into_iter().zip(response.target_text_list) {
+            result[i] = text;
+        }
+    }
+    Ok(result)
+}
+
+// group indexes of non empty texts into requests under `MAX_BATCH_CHARS`
+fn chunks(texts: &[String]) -> Vec<Vec<usize>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut size = 0;
+    for (i, text) in texts.iter().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let len = text.chars().count();
+        if !current.is_empty() && size + len > MAX_BATCH_CHARS {
+            chunks.push(std::mem::take(&mut current));
+            size = 0;
+        }
+        current.push(i);
+        size += len;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
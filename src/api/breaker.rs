@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{Error, Result};
+
+/// When a circuit of [`TencentClient::with_circuit_breaker`] opens and for
+/// how long
+///
+/// [`TencentClient::with_circuit_breaker`]: crate::TencentClient::with_circuit_breaker
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// calls are counted in windows of this length
+    pub window: Duration,
+    /// calls needed in a window before the failure rate is considered
+    pub min_calls: u32,
+    /// failed share of calls, between 0 and 1, opening the circuit
+    pub failure_rate: f64,
+    /// how long an open circuit fails fast before letting a probe through
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(30),
+            min_calls: 10,
+            failure_rate: 0.5,
+            open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// calls go through
+    Closed,
+    /// calls fail fast with [`Error::CircuitOpen`]
+    Open,
+    /// a single probe call goes through, its outcome closes or reopens
+    HalfOpen,
+}
+
+/// Calls are tracked per service, region and action
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CircuitKey {
+    pub service: &'static str,
+    pub region: String,
+    pub action: &'static str,
+}
+
+impl fmt::Display for CircuitKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.service, self.region, self.action)
+    }
+}
+
+/// A state change, as reported to the delegate
+pub(crate) type Transition = (CircuitState, CircuitState);
+
+struct Circuit {
+    state: CircuitState,
+    window_start: Instant,
+    calls: u32,
+    failures: u32,
+    opened_at: Instant,
+    probing: bool,
+}
+
+impl Circuit {
+    fn new(now: Instant) -> Self {
+        Self {
+            state: CircuitState::Closed,
+            window_start: now,
+            calls: 0,
+            failures: 0,
+            opened_at: now,
+            probing: false,
+        }
+    }
+}
+
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<CircuitKey, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<CircuitKey, Circuit>> {
+        self.circuits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Let a call through, or fail fast while the circuit is open
+    pub fn acquire(&self, key: CircuitKey) -> Result<(Permit<'_>, Option<Transition>)> {
+        let now = Instant::now();
+        let mut circuits = self.lock();
+        let circuit = circuits
+            .entry(key.clone())
+            .or_insert_with(|| Circuit::new(now));
+        let mut transition = None;
+        if circuit.state == CircuitState::Open
+            && now.duration_since(circuit.opened_at) >= self.config.open_for
+        {
+            circuit.state = CircuitState::HalfOpen;
+            transition = Some((CircuitState::Open, CircuitState::HalfOpen));
+        }
+        let probe = match circuit.state {
+            CircuitState::Closed => false,
+            CircuitState::HalfOpen if !circuit.probing => {
+                circuit.probing = true;
+                true
+            }
+            CircuitState::HalfOpen | CircuitState::Open => {
+                return Err(Error::CircuitOpen(key.to_string()));
+            }
+        };
+        drop(circuits);
+        let permit = Permit {
+            breaker: self,
+            key,
+            probe,
+            recorded: false,
+        };
+        Ok((permit, transition))
+    }
+}
+
+/// A call let through by [`CircuitBreaker::acquire`]
+pub(crate) struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    key: CircuitKey,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    pub fn key(&self) -> &CircuitKey {
+        &self.key
+    }
+
+    /// count the outcome of the call
+    pub fn record(mut self, success: bool) -> Option<Transition> {
+        self.recorded = true;
+        let config = &self.breaker.config;
+        let now = Instant::now();
+        let mut circuits = self.breaker.lock();
+        let circuit = circuits.get_mut(&self.key)?;
+        let from = circuit.state;
+        if self.probe {
+            circuit.probing = false;
+            *circuit = if success {
+                Circuit::new(now)
+            } else {
+                Circuit {
+                    state: CircuitState::Open,
+                    opened_at: now,
+                    ..Circuit::new(now)
+                }
+            };
+        } else if circuit.state == CircuitState::Closed {
+            if now.duration_since(circuit.window_start) >= config.window {
+                circuit.window_start = now;
+                circuit.calls = 0;
+                circuit.failures = 0;
+            }
+            circuit.calls += 1;
+            circuit.failures += u32::from(!success);
+            let rate = circuit.failures as f64 / circuit.calls as f64;
+            if circuit.calls >= config.min_calls && rate >= config.failure_rate {
+                circuit.state = CircuitState::Open;
+                circuit.opened_at = now;
+            }
+        }
+        (from != circuit.state).then_some((from, circuit.state))
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        // an abandoned probe must not keep the circuit half open forever
+        if self.probe && !self.recorded {
+            if let Some(circuit) = self.breaker.lock().get_mut(&self.key) {
+                circuit.probing = false;
+            }
+        }
+    }
+}
+
+/// Whether the outcome of a call hints at an outage rather than a bad request,
+/// the body of a streamed response is only its start
+pub(crate) fn is_outage(result: std::result::Result<&[u8], &Error>) -> bool {
+    match result {
+        Ok(body) => {
+            let Ok(value) = serde_json::from_slice::<serde_json::Value>(body) else {
+                return false;
+            };
+            let code = value["Response"]["Error"]["Code"]
+                .as_str()
+                .unwrap_or_default();
+            [
+                "InternalError",
+                "RequestLimitExceeded",
+                "ResourceUnavailable",
+                "ServiceUnavailable",
+            ]
+            .iter()
+            .any(|prefix| code.starts_with(prefix))
+        }
+        Err(Error::Failure(response)) => {
+            response.status().is_server_error() || response.status().as_u16() == 429
+        }
+        Err(Error::HttpError(_) | Error::Middleware(_)) => true,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circuit_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            min_calls: 2,
+            open_for: Duration::ZERO,
+            ..Default::default()
+        });
+        let key = CircuitKey {
+            service: "tmt",
+            region: "ap-guangzhou".to_string(),
+            action: "TextTranslate",
+        };
+
+        let (permit, _) = breaker.acquire(key.clone()).unwrap();
+        assert_eq!(permit.record(false), None);
+        let (permit, _) = breaker.acquire(key.clone()).unwrap();
+        assert_eq!(
+            permit.record(false),
+            Some((CircuitState::Closed, CircuitState::Open))
+        );
+
+        // open_for has passed, a single probe goes through
+        let (probe, transition) = breaker.acquire(key.clone()).unwrap();
+        assert_eq!(
+            transition,
+            Some((CircuitState::Open, CircuitState::HalfOpen))
+        );
+        assert!(matches!(
+            breaker.acquire(key.clone()),
+            Err(Error::CircuitOpen(_))
+        ));
+        assert_eq!(
+            probe.record(true),
+            Some((CircuitState::HalfOpen, CircuitState::Closed))
+        );
+        assert!(breaker.acquire(key).is_ok());
+    }
+
+    #[test]
+    fn outages() {
+        let body = |code: &str| format!(r#"{{"Response":{{"Error":{{"Code":"{code}"}}}}}}"#);
+        assert!(is_outage(Ok(
+            body("InternalError.BackendTimeout").as_bytes()
+        )));
+        assert!(!is_outage(Ok(
+            body("AuthFailure.SignatureFailure").as_bytes()
+        )));
+        // the start of a streamed document
+        assert!(!is_outage(Ok(br#"{"Response":{"Data":{"FileData":"#)));
+        // a cancelled call says nothing about the api
+        assert!(!is_outage(Err(&Error::Cancelled)));
+        let failure = |status: u16| {
+            let mut response = hyper::Response::new(hyper::Body::empty());
+            *response.status_mut() = hyper::StatusCode::from_u16(status).unwrap();
+            Error::Failure(Box::new(response))
+        };
+        assert!(is_outage(Err(&failure(503))));
+        assert!(is_outage(Err(&failure(429))));
+        assert!(!is_outage(Err(&failure(400))));
+    }
+}
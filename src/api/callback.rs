@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    client::connect::Connection,
+    service::{make_service_fn, service_fn, Service},
+    Body, Method, Request, Response, Server, StatusCode, Uri,
+};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+
+use super::{
+    decode_response, read_body, FileTranslateCall, FileTranslateResponse, FileTranslateStatus,
+    MAX_DOCUMENT_SIZE,
+};
+use crate::{Error, Result};
+
+/// largest callback body accepted, a finished task may carry the translated
+/// document base64 encoded
+const MAX_CALLBACK_SIZE: usize = (MAX_DOCUMENT_SIZE as usize).div_ceil(3) * 4 + (64 << 10);
+/// early notifications kept at most
+const EARLY_CAPACITY: usize = 256;
+/// how long an early notification waits for its task to be registered
+const EARLY_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct Registry {
+    /// tasks waiting for their notification
+    pending: HashMap<String, oneshot::Sender<FileTranslateStatus>>,
+    /// notifications which arrived before their task was registered
+    early: HashMap<String, (Instant, FileTranslateStatus)>,
+    /// tasks being started, whose ids are not known yet
+    starting: usize,
+}
+
+impl Registry {
+    /// false if the task is neither registered nor possibly being started
+    fn notify(&mut self, status: FileTranslateStatus) -> bool {
+        if let Some(tx) = self.pending.remove(&status.task_id) {
+            let _ = tx.send(status);
+            return true;
+        }
+        if self.starting == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        self.early
+            .retain(|_, (arrived, _)| now.duration_since(*arrived) < EARLY_TTL);
+        if self.early.len() >= EARLY_CAPACITY && !self.early.contains_key(&status.task_id) {
+            return false;
+        }
+        self.early.insert(status.task_id.clone(), (now, status));
+        true
+    }
+
+    fn register(&mut self, task_id: String) -> oneshot::Receiver<FileTranslateStatus> {
+        let (tx, rx) = oneshot::channel();
+        match self.early.remove(&task_id) {
+            Some((_, status)) => {
+                let _ = tx.send(status);
+            }
+            None => {
+                self.pending.insert(task_id, tx);
+            }
+        }
+        rx
+    }
+}
+
+/// a task being started, its callback may beat the response carrying its id
+struct Starting<'a>(&'a Mutex<Registry>);
+
+impl<'a> Starting<'a> {
+    fn new(registry: &'a Mutex<Registry>) -> Self {
+        lock(registry).starting += 1;
+        Self(registry)
+    }
+}
+
+impl Drop for Starting<'_> {
+    fn drop(&mut self) {
+        let mut registry = lock(self.0);
+        registry.starting -= 1;
+        if registry.starting == 0 {
+            registry.early.clear();
+        }
+    }
+}
+
+/// An embedded http server receiving `FileTranslate` completion callbacks.
+///
+/// Tencent Cloud must be able to reach it, pass its public address as the
+/// `callback_url` of [`FileTranslateCall`]. The server stops when dropped.
+///
+/// Callbacks are only accepted for tasks passed to
+/// [`wait_for`](Self::wait_for), or while
+/// [`doit_with_callback`](FileTranslateCall::doit_with_callback) is starting a
+/// task, others are answered with `404 Not Found`.
+pub struct CallbackReceiver {
+    addr: SocketAddr,
+    registry: Arc<Mutex<Registry>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl CallbackReceiver {
+    /// start listening on `addr`, use port 0 to pick a free port
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let registry = Arc::new(Mutex::new(Registry::default()));
+        let shared = registry.clone();
+        let make_service = make_service_fn(move |_| {
+            let registry = shared.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(registry.clone(), req))) }
+        });
+        let server = Server::try_bind(&addr)
+            .map_err(Error::HttpError)?
+            .serve(make_service);
+        let addr = server.local_addr();
+        let (tx, rx) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = rx.await;
+        }));
+        Ok(Self {
+            addr,
+            registry,
+            _shutdown: tx,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// future resolved when the callback of `task_id` arrives
+    pub fn wait_for(&self, task_id: impl Into<String>) -> PendingFileTranslate {
+        let task_id = task_id.into();
+        let rx = lock(&self.registry).register(task_id.clone());
+        PendingFileTranslate {
+            task_id,
+            rx,
+            registry: Arc::downgrade(&self.registry),
+        }
+    }
+}
+
+fn lock(registry: &Mutex<Registry>) -> std::sync::MutexGuard<'_, Registry> {
+    // the registry stays consistent even if a holder panicked
+    registry.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn handle(
+    registry: Arc<Mutex<Registry>>,
+    req: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if req.method() != Method::POST {
+        return Ok(reply(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let status = match read_body(req.into_body(), Some(MAX_CALLBACK_SIZE)).await {
+        Ok(bytes) => parse_callback(&bytes),
+        Err(Error::ResponseSizeLimitExceeded(..)) => {
+            return Ok(reply(StatusCode::PAYLOAD_TOO_LARGE))
+        }
+        Err(_) => None,
+    };
+    let Some(status) = status else {
+        return Ok(reply(StatusCode::BAD_REQUEST));
+    };
+    if lock(&registry).notify(status) {
+        Ok(reply(StatusCode::OK))
+    } else {
+        Ok(reply(StatusCode::NOT_FOUND))
+    }
+}
+
+fn reply(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// the status may come bare, wrapped in `Data`, or like an api response
+fn parse_callback(body: &[u8]) -> Option<FileTranslateStatus> {
+    let mut value: Value = serde_json::from_slice(body).ok()?;
+    if let Some(response) = value.get_mut("Response") {
+        value = response.take();
+    }
+    if let Some(data) = value.get_mut("Data") {
+        value = data.take();
+    }
+    serde_json::from_value(value).ok()
+}
+
+/// Completion of a file translate task, resolved by [`CallbackReceiver`].
+/// Dropping it stops waiting for the callback.
+pub struct PendingFileTranslate {
+    task_id: String,
+    rx: oneshot::Receiver<FileTranslateStatus>,
+    /// weak, so dropping the receiver still resolves this with an error
+    registry: Weak<Mutex<Registry>>,
+}
+
+impl PendingFileTranslate {
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+}
+
+impl Drop for PendingFileTranslate {
+    fn drop(&mut self) {
+        let Some(registry) = self.registry.upgrade() else {
+            return;
+        };
+        // a later `wait_for` of the same task holds an open sender, keep it
+        self.rx.close();
+        let mut registry = lock(&registry);
+        if registry
+            .pending
+            .get(&self.task_id)
+            .is_some_and(|tx| tx.is_closed())
+        {
+            registry.pending.remove(&self.task_id);
+        }
+    }
+}
+
+impl Future for PendingFileTranslate {
+    type Output = Result<FileTranslateStatus>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|status| match status {
+            Ok(status) if status.is_failed() => Err(Error::TaskFailed(
+                status.task_id,
+                status.message.unwrap_or_default(),
+            )),
+            Ok(status) => Ok(status),
+            // the sender was dropped with the registry, i.e. the `CallbackReceiver`
+            Err(_) => Err(Error::Cancelled),
+        })
+    }
+}
+
+impl<'a, S> FileTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Start the task and return a future resolved by the callback `receiver`
+    /// gets. `callback_url` must point at `receiver`.
+    pub async fn doit_with_callback(
+        self,
+        receiver: &CallbackReceiver,
+    ) -> Result<PendingFileTranslate> {
+        let starting = Starting::new(&receiver.registry);
+        let body = self.doit(|b| b).await?;
+        let task_id = decode_response::<FileTranslateResponse>(&body)?
+            .data
+            .task_id;
+        let pending = receiver.wait_for(task_id);
+        drop(starting);
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn callback_resolves_pending_task() {
+        let receiver = CallbackReceiver::bind(([127, 0, 0, 1], 0).into())
+            .await
+            .unwrap();
+        let pending = receiver.wait_for("task-1");
+
+        let client = hyper::Client::new();
+        let request = Request::post(format!("http://{}/", receiver.local_addr()))
+            .body(Body::from(
+                r#"{"Data":{"TaskId":"task-1","Status":"success","Progress":100}}"#,
+            ))
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let status = pending.await.unwrap();
+        assert_eq!(status.task_id, "task-1");
+        assert_eq!(status.progress, 100);
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_unknown_and_oversized() {
+        let receiver = CallbackReceiver::bind(([127, 0, 0, 1], 0).into())
+            .await
+            .unwrap();
+        let client = hyper::Client::new();
+        let post = |body: Body| {
+            let request = Request::post(format!("http://{}/", receiver.local_addr()))
+                .body(body)
+                .unwrap();
+            client.request(request)
+        };
+        let unknown = r#"{"TaskId":"task-2","Status":"success"}"#;
+        assert_eq!(
+            post(Body::from(unknown)).await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+        let huge = vec![b' '; MAX_CALLBACK_SIZE + 1];
+        assert_eq!(
+            post(Body::from(huge)).await.unwrap().status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        // while a task is starting its callback is kept until registered
+        let starting = Starting::new(&receiver.registry);
+        assert_eq!(
+            post(Body::from(unknown)).await.unwrap().status(),
+            StatusCode::OK
+        );
+        let pending = receiver.wait_for("task-2");
+        drop(starting);
+        assert_eq!(pending.await.unwrap().task_id, "task-2");
+        assert!(lock(&receiver.registry).early.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_pending_tasks_are_forgotten() {
+        let receiver = CallbackReceiver::bind(([127, 0, 0, 1], 0).into())
+            .await
+            .unwrap();
+        drop(receiver.wait_for("task-3"));
+        assert!(lock(&receiver.registry).pending.is_empty());
+
+        // dropping a superseded wait leaves the newer one registered
+        let first = receiver.wait_for("task-4");
+        let second = receiver.wait_for("task-4");
+        assert!(matches!(first.await, Err(Error::Cancelled)));
+        assert!(lock(&receiver.registry).pending.contains_key("task-4"));
+        drop(second);
+        assert!(lock(&receiver.registry).pending.is_empty());
+
+        // a pending task outliving its receiver fails instead of waiting
+        let pending = receiver.wait_for("task-5");
+        drop(receiver);
+        assert!(matches!(pending.await, Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn early_notifications_are_bounded() {
+        let mut registry = Registry {
+            starting: 1,
+            ..Default::default()
+        };
+        let status = |id: usize| FileTranslateStatus {
+            task_id: format!("task-{id}"),
+            status: "success".to_string(),
+            file_data: None,
+            message: None,
+            progress: 100,
+            used_amount: None,
+        };
+        for id in 0..EARLY_CAPACITY {
+            assert!(registry.notify(status(id)));
+        }
+        assert!(!registry.notify(status(EARLY_CAPACITY)));
+
+        // expired notifications make room
+        for (arrived, _) in registry.early.values_mut() {
+            *arrived -= EARLY_TTL;
+        }
+        assert!(registry.notify(status(EARLY_CAPACITY)));
+        assert_eq!(registry.early.len(), 1);
+    }
+}
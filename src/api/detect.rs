@@ -0,0 +1,192 @@
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    decode_response, Language, LanguageDetectResponse, TextTranslateResponse, TranslateKind,
+    TranslateMethods,
+};
+use crate::{client::Delegate, Result, TencentClient};
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// detect the language of text and translate it only when it differs
+    /// from the target
+    pub fn detect_translate(&self) -> DetectTranslateCallBuilder<'a, S> {
+        DetectTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Detect the language of `text` with `LanguageDetect`, then translate it
+/// with `TextTranslate` unless it is already in `target`.
+///
+/// Detection is unreliable on short snippets, text shorter than
+/// `min_detect_chars` is translated from `auto` without detection. A detected
+/// language the api can not translate to `target` also falls back to `auto`.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct DetectTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// abort detection or translation, whichever is running, once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    #[builder(setter(into))]
+    text: String,
+    /// counted in chars
+    #[builder(default = "10")]
+    min_detect_chars: usize,
+}
+
+impl<'a, S> DetectTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(Some(Language::Auto), self.target)
+    }
+}
+
+/// Result of [`DetectTranslateCall`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectTranslateOutput {
+    /// `None` when detection was skipped or returned an unknown language
+    pub detected: Option<Language>,
+    /// the translation, or the original text when it was already in target
+    pub text: String,
+    /// false when translation was skipped
+    pub translated: bool,
+}
+
+impl<'a, S> DetectTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub async fn doit(mut self) -> Result<DetectTranslateOutput> {
+        if self.text.trim().is_empty() {
+            return Ok(DetectTranslateOutput {
+                detected: None,
+                text: self.text,
+                translated: false,
+            });
+        }
+
+        let mut detected = None;
+        if self.text.chars().count() >= self.min_detect_chars {
+            let mut builder = self
+                .client
+                .translate()
+                .language_detect()
+                .project_id(self.project_id)
+                .region(self.region.as_str())
+                .text(self.text.as_str());
+            if let Some(dlg) = self.delegate.as_deref_mut() {
+                builder = builder.delegate(dlg);
+            }
+            if let Some(ref token) = self.cancel {
+                builder = builder.cancel(token.clone());
+            }
+            let body = builder.build()?.doit(|b| b).await?;
+            detected = decode_response::<LanguageDetectResponse>(&body)?.language();
+        }
+
+        if detected == Some(self.target) {
+            return Ok(DetectTranslateOutput {
+                detected,
+                text: self.text,
+                translated: false,
+            });
+        }
+        let source = detected
+            .filter(|&source| TranslateKind::Text.supports(source, self.target))
+            .unwrap_or(Language::Auto);
+
+        let mut builder = self
+            .client
+            .translate()
+            .text_translate()
+            .project_id(self.project_id)
+            .source(source)
+            .target(self.target)
+            .region(self.region)
+            .source_text(self.text);
+        if let Some(dlg) = self.delegate {
+            builder = builder.delegate(dlg);
+        }
+        if let Some(ref token) = self.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        let response: TextTranslateResponse = decode_response(&body)?;
+        Ok(DetectTranslateOutput {
+            detected,
+            text: response.target_text,
+            translated: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock;
+
+    async fn run(client: &mock::MockClient, text: &str) -> DetectTranslateOutput {
+        client
+            .translate()
+            .detect_translate()
+            .project_id(0u32)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .text(text)
+            .min_detect_chars(5usize)
+            .build()
+            .unwrap()
+            .doit()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn short_text_is_translated_from_auto() {
+        let (client, seen) = mock::sequence(vec![
+            r#"{"Response":{"RequestId":"1","Source":"xx","Target":"zh","TargetText":"嗨"}}"#,
+        ]);
+        let output = run(&client, "hi").await;
+        assert_eq!(output.detected, None);
+        assert_eq!(output.text, "嗨");
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].0, "TextTranslate");
+        assert!(seen[0].1.contains(r#""Source":"auto""#), "{}", seen[0].1);
+    }
+
+    #[tokio::test]
+    async fn detected_language_is_used_or_skipped() {
+        let (client, seen) = mock::sequence(vec![r#"{"Response":{"RequestId":"1","Lang":"zh"}}"#]);
+        let output = run(&client, "你好，世界").await;
+        assert_eq!(output.detected, Some(Language::Zh));
+        assert!(!output.translated);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        let (client, seen) = mock::sequence(vec![
+            r#"{"Response":{"RequestId":"1","Lang":"en"}}"#,
+            r#"{"Response":{"RequestId":"2","Source":"en","Target":"zh","TargetText":"你好，世界"}}"#,
+        ]);
+        let output = run(&client, "hello world").await;
+        assert_eq!(output.detected, Some(Language::En));
+        assert_eq!(output.text, "你好，世界");
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "LanguageDetect");
+        assert!(seen[1].1.contains(r#""Source":"en""#), "{}", seen[1].1);
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::Path;
+
+use super::{utils::to_base64, FileTranslateCallBuilder, TranslateMethods};
+use crate::{Error, Result};
+
+/// `FileTranslate` rejects documents larger than this
+pub const MAX_DOCUMENT_SIZE: u64 = 5 << 20;
+
+/// Document types accepted by `FileTranslate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentType {
+    Pdf,
+    Docx,
+    /// legacy word, sent as `docx` with basic document type `doc`
+    Doc,
+    Pptx,
+    /// legacy powerpoint, sent as `pptx` with basic document type `ppt`
+    Ppt,
+    Xlsx,
+    /// legacy excel, sent as `xlsx` with basic document type `xls`
+    Xls,
+    Txt,
+    Xml,
+    Html,
+    Markdown,
+    Properties,
+}
+
+impl DocumentType {
+    /// value of `DocumentType`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentType::Pdf => "pdf",
+            DocumentType::Docx | DocumentType::Doc => "docx",
+            DocumentType::Pptx | DocumentType::Ppt => "pptx",
+            DocumentType::Xlsx | DocumentType::Xls => "xlsx",
+            DocumentType::Txt => "txt",
+            DocumentType::Xml => "xml",
+            DocumentType::Html => "html",
+            DocumentType::Markdown => "markdown",
+            DocumentType::Properties => "properties",
+        }
+    }
+
+    /// value of `BasicDocumentType`, only set for legacy office formats
+    pub fn basic_document_type(&self) -> Option<&'static str> {
+        match self {
+            DocumentType::Doc => Some("doc"),
+            DocumentType::Ppt => Some("ppt"),
+            DocumentType::Xls => Some("xls"),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let kind = match ext.to_ascii_lowercase().as_str() {
+            "pdf" => DocumentType::Pdf,
+            "docx" => DocumentType::Docx,
+            "doc" => DocumentType::Doc,
+            "pptx" => DocumentType::Pptx,
+            "ppt" => DocumentType::Ppt,
+            "xlsx" => DocumentType::Xlsx,
+            "xls" => DocumentType::Xls,
+            "txt" | "text" => DocumentType::Txt,
+            "xml" => DocumentType::Xml,
+            "html" | "htm" => DocumentType::Html,
+            "md" | "markdown" => DocumentType::Markdown,
+            "properties" => DocumentType::Properties,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Tell the type by content, the extension of `path` breaks ties between
+    /// formats sharing a container and names plain text flavours.
+    pub fn detect(path: impl AsRef<Path>, content: &[u8]) -> Option<Self> {
+        let by_ext = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension);
+
+        if content.starts_with(b"%PDF") {
+            return Some(DocumentType::Pdf);
+        }
+        if content.starts_with(b"PK\x03\x04") {
+            // office open xml is a zip, look for its part names
+            return if contains(content, b"word/") {
+                Some(DocumentType::Docx)
+            } else if contains(content, b"ppt/") {
+                Some(DocumentType::Pptx)
+            } else if contains(content, b"xl/") {
+                Some(DocumentType::Xlsx)
+            } else {
+                None
+            };
+        }
+        if content.starts_with(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+            // compound file, look for stream names (utf-16)
+            let utf16 = |name: &str| -> Vec<u8> {
+                name.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+            };
+            return if contains(content, &utf16("WordDocument")) {
+                Some(DocumentType::Doc)
+            } else if contains(content, &utf16("PowerPoint Document")) {
+                Some(DocumentType::Ppt)
+            } else if contains(content, &utf16("Workbook")) || contains(content, &utf16("Book")) {
+                Some(DocumentType::Xls)
+            } else {
+                by_ext.filter(|kind| kind.basic_document_type().is_some())
+            };
+        }
+
+        let text = std::str::from_utf8(content).ok()?;
+        let head = text
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .chars()
+            .take(64)
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if head.starts_with("<!doctype html") || head.starts_with("<html") {
+            return Some(DocumentType::Html);
+        }
+        match by_ext {
+            Some(
+                kind @ (DocumentType::Html | DocumentType::Markdown | DocumentType::Properties),
+            ) => Some(kind),
+            _ if head.starts_with("<?xml") => Some(DocumentType::Xml),
+            _ => Some(DocumentType::Txt),
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// A document read from disk, checked against [`MAX_DOCUMENT_SIZE`]
+pub(crate) struct Document {
+    pub kind: DocumentType,
+    pub data: Vec<u8>,
+}
+
+impl Document {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > MAX_DOCUMENT_SIZE {
+            return Err(Error::UploadSizeLimitExceeded(size, MAX_DOCUMENT_SIZE));
+        }
+        let data = tokio::fs::read(path).await?;
+        let kind = DocumentType::detect(path, &data).ok_or_else(|| {
+            Error::InvalidArgument(format!("unsupported document type: {}", path.display()))
+        })?;
+        Ok(Self { kind, data })
+    }
+}
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate a document on disk, its type is detected and its content
+    /// uploaded as base64
+    pub async fn file_translate_from_path(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<FileTranslateCallBuilder<'a, S>> {
+        let document = Document::load(path.as_ref()).await?;
+        let mut builder = self
+            .file_translate()
+            .document_type(document.kind.as_str())
+            .source_type(1u8)
+            .data(to_base64(document.data));
+        if let Some(basic) = document.kind.basic_document_type() {
+            builder = builder.basic_document_type(basic);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_document_type() {
+        let detect = DocumentType::detect;
+        assert_eq!(detect("a.bin", b"%PDF-1.7"), Some(DocumentType::Pdf));
+        assert_eq!(
+            detect("a.zip", b"PK\x03\x04....word/document.xml"),
+            Some(DocumentType::Docx)
+        );
+        assert_eq!(
+            detect("a", b"PK\x03\x04....xl/workbook.xml"),
+            Some(DocumentType::Xlsx)
+        );
+        assert_eq!(
+            detect("a.txt", b"<!DOCTYPE html><p>hi"),
+            Some(DocumentType::Html)
+        );
+        assert_eq!(detect("a.md", b"# title"), Some(DocumentType::Markdown));
+        assert_eq!(
+            detect("a", b"<?xml version=\"1.0\"?>"),
+            Some(DocumentType::Xml)
+        );
+        assert_eq!(detect("a.docx", b"plain"), Some(DocumentType::Txt));
+        assert_eq!(detect("a.png", b"\x89PNG\r\n\x1a\n\xff"), None);
+        assert_eq!(DocumentType::Doc.as_str(), "docx");
+        assert_eq!(DocumentType::Doc.basic_document_type(), Some("doc"));
+    }
+}
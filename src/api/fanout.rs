@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use futures_util::{stream, StreamExt};
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    decode_response, middleware::Pacer, utils::unless_cancelled, Language, TextTranslateResponse,
+    TranslateKind, TranslateMethods,
+};
+use crate::{
+    client::Delegate,
+    hooks::{DelegateHooks, Hooks},
+    Result, TencentClient,
+};
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate one text into several languages at once
+    pub fn fan_out_translate(&self) -> FanOutTranslateCallBuilder<'a, S> {
+        FanOutTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Translate `source_text` into every language of `targets` with concurrent
+/// `TextTranslate` requests. At most `concurrency` requests are in flight.
+///
+/// The requests go through the client, paced by
+/// [`with_rate_limit`](TencentClient::with_rate_limit) like any other call.
+/// `requests_per_second` paces the fan-out on its own, for clients without a
+/// limit.
+///
+/// The requests share `delegate`, taking turns, or `hooks`.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct FanOutTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// abort the requests still running or waiting
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    targets: Vec<Language>,
+    #[builder(setter(into))]
+    region: String,
+    #[builder(setter(into))]
+    source_text: String,
+    #[builder(setter(into, strip_option), default)]
+    untranslated_text: Option<String>,
+    #[builder(setter(into, strip_option), default)]
+    term_repo_id_list: Option<Vec<String>>,
+    #[builder(setter(into, strip_option), default)]
+    sent_repo_id_list: Option<Vec<String>>,
+    #[builder(default = "5")]
+    concurrency: usize,
+    #[builder(setter(strip_option), default)]
+    requests_per_second: Option<u32>,
+}
+
+impl<'a, S> FanOutTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.concurrency == Some(0) || self.requests_per_second == Some(Some(0)) {
+            return Err("concurrency and requests_per_second must not be 0".to_string());
+        }
+        for &target in self.targets.iter().flatten() {
+            TranslateKind::Text.validate(self.source, Some(target))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S> FanOutTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// the result of every target, a failed language does not fail the others
+    pub async fn doit(mut self) -> BTreeMap<Language, Result<TextTranslateResponse>> {
+        self.targets.sort();
+        self.targets.dedup();
+        let shared = self.delegate.take().map(DelegateHooks::new);
+        let hooks = match &shared {
+            Some(shared) => Some(shared as &dyn Hooks),
+            None => self.hooks,
+        };
+        let pacer = self
+            .requests_per_second
+            .map(|num| Pacer::new(num, Duration::from_secs(1)));
+        let this = &self;
+        let pacer = &pacer;
+        stream::iter(self.targets.iter().copied())
+            .map(|target| async move {
+                if let Some(pacer) = pacer {
+                    let wait = tokio::time::sleep_until(pacer.next());
+                    if let Err(err) = unless_cancelled(this.cancel.as_ref(), wait).await {
+                        return (target, Err(err));
+                    }
+                }
+                (target, this.translate(target, hooks).await)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    async fn translate(
+        &self,
+        target: Language,
+        hooks: Option<&dyn Hooks>,
+    ) -> Result<TextTranslateResponse> {
+        let mut builder = self
+            .client
+            .translate()
+            .text_translate()
+            .project_id(self.project_id)
+            .source(self.source)
+            .target(target)
+            .region(self.region.as_str())
+            .source_text(self.source_text.as_str());
+        if let Some(ref text) = self.untranslated_text {
+            builder = builder.untranslated_text(text.as_str());
+        }
+        if let Some(ref list) = self.term_repo_id_list {
+            builder = builder.term_repo_id_list(list.clone());
+        }
+        if let Some(ref list) = self.sent_repo_id_list {
+            builder = builder.sent_repo_id_list(list.clone());
+        }
+        if let Some(hooks) = hooks {
+            builder = builder.hooks(hooks);
+        }
+        if let Some(ref token) = self.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        decode_response(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{api::mock, Error};
+
+    const REPLY: &str =
+        r#"{"Response":{"RequestId":"1","Source":"en","Target":"zh","TargetText":"嗨"}}"#;
+
+    fn call<'a>(
+        client: &'a mock::MockClient,
+    ) -> FanOutTranslateCallBuilder<'a, hyper::client::HttpConnector> {
+        client
+            .translate()
+            .fan_out_translate()
+            .project_id(0u32)
+            .source(Language::En)
+            .targets(vec![Language::Zh, Language::Ja, Language::Ko])
+            .region("ap-guangzhou")
+            .source_text("hi")
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        begun: usize,
+        succeeded: usize,
+    }
+
+    impl Delegate for Counter {
+        fn begin(&mut self, _info: crate::client::MethodInfo) {
+            self.begun += 1;
+        }
+
+        fn finished(&mut self, is_success: bool) {
+            self.succeeded += usize::from(is_success);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_share_the_delegate() {
+        let (client, seen) = mock::sequence(vec![REPLY]);
+        let mut counter = Counter::default();
+        let results = call(&client)
+            .delegate(&mut counter)
+            .build()
+            .unwrap()
+            .doit()
+            .await;
+        assert_eq!(results.len(), 3);
+        assert!(results.values().all(Result::is_ok));
+        assert_eq!((counter.begun, counter.succeeded), (3, 3));
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn requests_are_paced_by_the_client() {
+        let (client, _) = mock::sequence(vec![REPLY]);
+        let client = client.with_rate_limit(1, Duration::from_millis(100));
+        let started = Instant::now();
+        let results = call(&client).build().unwrap().doit().await;
+        assert!(results.values().all(Result::is_ok));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_fail() {
+        let (client, seen) = mock::sequence(vec![REPLY]);
+        let token = CancellationToken::new();
+        token.cancel();
+        let results = call(&client)
+            .cancel(token)
+            .requests_per_second(1u32)
+            .build()
+            .unwrap()
+            .doit()
+            .await;
+        assert!(results.values().all(|r| matches!(r, Err(Error::Cancelled))));
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    decode_response,
+    document::Document,
+    utils::{from_base64, to_base64, unless_cancelled},
+    FileTranslateDataResponse, FileTranslateResponse, FileTranslateStatus, Language, TranslateKind,
+    TranslateMethods,
+};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// upload a document, wait for its translation and save the result
+    pub fn translate_file(&self) -> TranslateFileCallBuilder<'a, S> {
+        TranslateFileCallBuilder::default().client(self.client)
+    }
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(&FileTranslateStatus) + Send + 'a>;
+
+/// Upload a document with `FileTranslate`, poll `GetFileTranslate` until the
+/// task finishes and write the translated document to `destination`.
+///
+/// Polling starts after `poll_interval` and the interval doubles after every
+/// poll up to `max_poll_interval`.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct TranslateFileCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// stop uploading or polling once cancelled, the task itself keeps running
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    path: PathBuf,
+    #[builder(setter(into))]
+    destination: PathBuf,
+    /// defaults to the type detected from `path`, see
+    /// [`DocumentType::detect`](super::DocumentType::detect)
+    #[builder(setter(into, strip_option), default)]
+    document_type: Option<String>,
+    #[builder(setter(into, strip_option), default)]
+    basic_document_type: Option<String>,
+    #[builder(default = "Duration::from_secs(2)")]
+    poll_interval: Duration,
+    #[builder(default = "Duration::from_secs(30)")]
+    max_poll_interval: Duration,
+    /// give up waiting after this long, the task itself is not cancelled
+    #[builder(default = "Duration::from_secs(600)")]
+    timeout: Duration,
+    /// called with every polled status
+    #[builder(setter(custom), default)]
+    progress: Option<ProgressFn<'a>>,
+}
+
+impl<'a, S> TranslateFileCallBuilder<'a, S> {
+    pub fn progress(mut self, f: impl FnMut(&FileTranslateStatus) + Send + 'a) -> Self {
+        self.progress = Some(Some(Box::new(f)));
+        self
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::File.validate(self.source, self.target)
+    }
+}
+
+impl<'a, S> TranslateFileCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// returns the final status of the task
+    pub async fn doit(mut self) -> Result<FileTranslateStatus> {
+        let document = Document::load(&self.path).await?;
+        let document_type = self
+            .document_type
+            .take()
+            .unwrap_or_else(|| document.kind.as_str().to_string());
+        let basic_document_type = self
+            .basic_document_type
+            .take()
+            .or_else(|| document.kind.basic_document_type().map(str::to_string));
+
+        let mut builder = self
+            .client
+            .translate()
+            .file_translate()
+            .source(self.source)
+            .target(self.target)
+            .document_type(document_type)
+            .source_type(1u8)
+            .data(to_base64(document.data));
+        if let Some(basic_document_type) = basic_document_type {
+            builder = builder.basic_document_type(basic_document_type);
+        }
+        if let Some(dlg) = self.delegate.as_deref_mut() {
+            builder = builder.delegate(dlg);
+        }
+        if let Some(ref token) = self.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        let task_id = decode_response::<FileTranslateResponse>(&body)?
+            .data
+            .task_id;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut interval = self.poll_interval;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::TaskTimeout(task_id));
+            }
+            let wait = tokio::time::sleep(interval.min(deadline - now));
+            unless_cancelled(self.cancel.as_ref(), wait).await?;
+            interval = (interval * 2).min(self.max_poll_interval);
+
+            let mut builder = self
+                .client
+                .translate()
+                .get_file_translate_data()
+                .task_id(task_id.as_str());
+            if let Some(dlg) = self.delegate.as_deref_mut() {
+                builder = builder.delegate(dlg);
+            }
+            if let Some(ref token) = self.cancel {
+                builder = builder.cancel(token.clone());
+            }
+            let body = builder.build()?.doit(|b| b).await?;
+            let status = decode_response::<FileTranslateDataResponse>(&body)?.data;
+            if let Some(ref mut progress) = self.progress {
+                progress(&status);
+            }
+
+            if status.is_failed() {
+                let message = status.message.unwrap_or_default();
+                return Err(Error::TaskFailed(task_id, message));
+            }
+            if status.is_success() {
+                let data = status.file_data.as_deref().unwrap_or_default();
+                let document = from_base64(data).map_err(|e| {
+                    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+                })?;
+                tokio::fs::write(&self.destination, document).await?;
+                return Ok(status);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::api::mock;
+
+    const TASK: &str = r#"{"Response":{"RequestId":"1","Data":{"TaskId":"t1"}}}"#;
+    const WAIT: &str = r#"{"Response":{"RequestId":"2","Data":{"TaskId":"t1","Status":"wait"}}}"#;
+
+    async fn run(
+        client: &mock::MockClient,
+        name: &str,
+        timeout: Duration,
+    ) -> (Result<FileTranslateStatus>, PathBuf) {
+        run_with(client, name, timeout, |b| b).await
+    }
+
+    async fn run_with<'a>(
+        client: &'a mock::MockClient,
+        name: &str,
+        timeout: Duration,
+        f: impl FnOnce(
+            TranslateFileCallBuilder<'a, hyper::client::HttpConnector>,
+        ) -> TranslateFileCallBuilder<'a, hyper::client::HttpConnector>,
+    ) -> (Result<FileTranslateStatus>, PathBuf) {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("tencent3-{}-{name}.txt", std::process::id()));
+        let destination = path.with_extension("zh.txt");
+        tokio::fs::write(&path, "hello").await.unwrap();
+        let builder = client
+            .translate()
+            .translate_file()
+            .source(Language::En)
+            .target(Language::Zh)
+            .path(&path)
+            .destination(&destination)
+            .poll_interval(Duration::from_millis(20))
+            .max_poll_interval(Duration::from_millis(50))
+            .timeout(timeout);
+        let result = f(builder).build().unwrap().doit().await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        (result, destination)
+    }
+
+    #[tokio::test]
+    async fn poll_backs_off_until_done() {
+        let polls = Arc::new(Mutex::new(Vec::new()));
+        let seen = polls.clone();
+        let client = mock::client(move |req| {
+            let body = if mock::action(&req) == "FileTranslate" {
+                TASK
+            } else {
+                let mut seen = seen.lock().unwrap();
+                seen.push(Instant::now());
+                match seen.len() {
+                    1..=3 => WAIT,
+                    // "5L2g5aW9" is 你好
+                    _ => {
+                        r#"{"Response":{"RequestId":"3","Data":{"TaskId":"t1","Status":"Success","FileData":"5L2g5aW9"}}}"#
+                    }
+                }
+            };
+            async move { Ok(mock::json(body)) }
+        });
+        let (result, destination) = run(&client, "done", Duration::from_secs(5)).await;
+        assert!(result.unwrap().is_success());
+        assert_eq!(
+            tokio::fs::read_to_string(&destination).await.unwrap(),
+            "你好"
+        );
+        tokio::fs::remove_file(&destination).await.unwrap();
+
+        let polls = polls.lock().unwrap();
+        let gaps = polls.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+        // 20ms doubled to 40ms, then capped at 50ms
+        for (gap, min) in gaps.iter().zip([40, 50, 50]) {
+            assert!(*gap >= Duration::from_millis(min), "{gaps:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_reports_failure_and_timeout() {
+        let (client, _) = mock::sequence(vec![
+            TASK,
+            r#"{"Response":{"RequestId":"2","Data":{"TaskId":"t1","Status":"fail","Message":"bad file"}}}"#,
+        ]);
+        let (result, _) = run(&client, "fail", Duration::from_secs(5)).await;
+        assert!(
+            matches!(result, Err(Error::TaskFailed(id, message)) if id == "t1" && message == "bad file")
+        );
+
+        let (client, actions) = mock::sequence(vec![TASK, WAIT]);
+        let (result, _) = run(&client, "timeout", Duration::from_millis(100)).await;
+        assert!(matches!(result, Err(Error::TaskTimeout(id)) if id == "t1"));
+        assert!(actions.lock().unwrap().len() > 2);
+    }
+
+    #[tokio::test]
+    async fn poll_stops_once_cancelled() {
+        let (client, actions) = mock::sequence(vec![TASK, WAIT]);
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+        let started = Instant::now();
+        let (result, _) = run_with(&client, "cancel", Duration::from_secs(30), |b| {
+            b.cancel(token)
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let polls = actions.lock().unwrap().len();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert_eq!(actions.lock().unwrap().len(), polls);
+    }
+}
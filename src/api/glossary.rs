@@ -0,0 +1,223 @@
+use serde_json::Value;
+
+use crate::{Error, Result};
+
+/// A client side term base.
+///
+/// Every configured term in the source text is swapped for a placeholder before
+/// the request is sent, and the placeholder is swapped for the configured
+/// rendering after the response arrives, so brand names and product terms
+/// survive translation even without a server side `TermRepoIDList`.
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    // (term, rendering), kept sorted by term length so longer terms win
+    entries: Vec<(String, String)>,
+}
+
+impl Glossary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `term` should be rendered as `translation` in the translated text
+    pub fn insert(&mut self, term: impl Into<String>, translation: impl Into<String>) -> &mut Self {
+        let term = term.into();
+        if term.is_empty() {
+            return self;
+        }
+        let translation = translation.into();
+        match self.entries.iter_mut().find(|(t, _)| *t == term) {
+            Some(entry) => entry.1 = translation,
+            None => {
+                self.entries.push((term, translation));
+                self.entries
+                    .sort_by_key(|(t, _)| std::cmp::Reverse(t.chars().count()));
+            }
+        }
+        self
+    }
+
+    /// `term` should be kept as is in the translated text
+    pub fn keep(&mut self, term: impl Into<String>) -> &mut Self {
+        let term = term.into();
+        self.insert(term.clone(), term)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// replace every configured term in `text` with a placeholder
+    ///
+    /// Terms only match as whole words, so `tmt` is left alone in `atmtb`.
+    /// Text that already looks like a placeholder is protected as well, so it
+    /// comes back unchanged.
+    pub fn protect(&self, text: &str) -> Protected {
+        let mut out = String::with_capacity(text.len());
+        let mut slots = Vec::new();
+        let mut prev = None;
+        let mut rest = text;
+        'outer: while let Some(ch) = rest.chars().next() {
+            if let Some(len) = placeholder_len(rest) {
+                out.push_str(&placeholder(slots.len()));
+                slots.push(rest[..len].to_string());
+                prev = rest[..len].chars().next_back();
+                rest = &rest[len..];
+                continue;
+            }
+            for (term, translation) in &self.entries {
+                let Some(tail) = rest.strip_prefix(term.as_str()) else {
+                    continue;
+                };
+                if glued(prev, term.chars().next())
+                    || glued(term.chars().next_back(), tail.chars().next())
+                {
+                    continue;
+                }
+                out.push_str(&placeholder(slots.len()));
+                slots.push(translation.clone());
+                prev = term.chars().next_back();
+                rest = tail;
+                continue 'outer;
+            }
+            out.push(ch);
+            prev = Some(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+        Protected { text: out, slots }
+    }
+}
+
+/// whether two adjacent characters belong to the same word
+fn glued(a: Option<char>, b: Option<char>) -> bool {
+    // scripts without spaces between words have no word boundary to check
+    let is_word = |c: char| {
+        c.is_alphanumeric()
+            && !matches!(c as u32, 0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0x20000..=0x3FFFF)
+    };
+    matches!((a, b), (Some(a), Some(b)) if is_word(a) && is_word(b))
+}
+
+pub(crate) fn placeholder(index: usize) -> String {
+    format!("__T{index}__")
+}
+
+/// length of the placeholder `text` starts with, if any
+fn placeholder_len(text: &str) -> Option<usize> {
+    let digits = text.strip_prefix("__T")?;
+    let count = digits.bytes().take_while(u8::is_ascii_digit).count();
+    (count > 0 && digits[count..].starts_with("__")).then_some(3 + count + 2)
+}
+
+/// Text with glossary terms replaced by placeholders
+#[derive(Debug, Clone)]
+pub struct Protected {
+    pub text: String,
+    slots: Vec<String>,
+}
+
+impl Protected {
+    pub(crate) fn from_parts(text: String, slots: Vec<String>) -> Self {
+        Self { text, slots }
+    }
+
+    /// whether every placeholder survived translation
+    pub(crate) fn is_intact(&self, translated: &str) -> bool {
+        (0..self.slots.len()).all(|i| translated.contains(&placeholder(i)))
+    }
+
+    /// put configured renderings back in place of the placeholders
+    pub fn restore(&self, translated: &str) -> String {
+        // a single pass, so renderings are never replaced again
+        let mut out = String::with_capacity(translated.len());
+        let mut rest = translated;
+        while let Some(start) = rest.find("__T") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let slot = placeholder_len(rest).and_then(|len| {
+                Some((
+                    len,
+                    self.slots.get(rest[3..len - 2].parse::<usize>().ok()?)?,
+                ))
+            });
+            match slot {
+                Some((len, rendering)) => {
+                    out.push_str(rendering);
+                    rest = &rest[len..];
+                }
+                None => {
+                    out.push_str("__T");
+                    rest = &rest[3..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// rewrite `TargetText`/`TargetTextList` of a raw api response with
+/// placeholders restored
+pub(crate) fn restore_response(body: Vec<u8>, protected: &[Protected]) -> Result<Vec<u8>> {
+    let mut value: Value = serde_json::from_slice(&body)
+        .map_err(|e| Error::JsonError(String::from_utf8_lossy(&body).into_owned(), e))?;
+    if let Some(response) = value.get_mut("Response") {
+        if let Some(Value::String(text)) = response.get_mut("TargetText") {
+            if let Some(p) = protected.first() {
+                *text = p.restore(text);
+            }
+        }
+        if let Some(Value::Array(list)) = response.get_mut("TargetTextList") {
+            for (item, p) in list.iter_mut().zip(protected) {
+                if let Value::String(text) = item {
+                    *text = p.restore(text);
+                }
+            }
+        }
+    }
+    serde_json::to_vec(&value).map_err(|e| Error::JsonError(format!("{value:?}"), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protect_and_restore() {
+        let mut glossary = Glossary::new();
+        glossary.keep("Tencent").insert("Tencent Cloud", "腾讯云");
+        let protected = glossary.protect("Tencent Cloud is part of Tencent.");
+        assert_eq!(protected.text, "__T0__ is part of __T1__.");
+        assert_eq!(
+            protected.restore("__T0__是__T1__的一部分。"),
+            "腾讯云是Tencent的一部分。"
+        );
+    }
+
+    #[test]
+    fn protect_whole_words_and_existing_placeholders() {
+        let mut glossary = Glossary::new();
+        glossary.insert("tmt", "TMT").insert("云", "cloud");
+        let protected = glossary.protect("atmtb tmt, 腾讯云 __T0__");
+        assert_eq!(protected.text, "atmtb __T0__, 腾讯__T1__ __T2__");
+        assert_eq!(
+            protected.restore("__T2__ __T1__ __T0__ __T9__"),
+            "__T0__ cloud TMT __T9__"
+        );
+    }
+
+    #[test]
+    fn restore_batch_response() {
+        let mut glossary = Glossary::new();
+        glossary.keep("tmt");
+        let protected = vec![glossary.protect("a tmt"), glossary.protect("b tmt")];
+        let body = r#"{"Response":{"TargetTextList":["甲 __T0__","乙 __T0__"]}}"#
+            .as_bytes()
+            .to_vec();
+        let body = restore_response(body, &protected).unwrap();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            r#"{"Response":{"TargetTextList":["甲 tmt","乙 tmt"]}}"#
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::VecDeque, future::Future, pin::pin, sync::Mutex, time::Duration};
+
+use futures_util::future::{select, Either};
+use tokio_util::sync::CancellationToken;
+
+use crate::Result;
+
+/// latencies kept to compute the hedge delay from
+const SAMPLES: usize = 256;
+
+/// When [`TencentClient::with_hedging`] sends a second request
+///
+/// [`TencentClient::with_hedging`]: crate::TencentClient::with_hedging
+#[derive(Debug, Clone)]
+pub struct HedgeConfig {
+    /// percentile, between 0 and 1, of the latency of recent successful first
+    /// requests after which the second request is sent, a call won by the
+    /// second request counts with its own latency as the least the first took
+    pub percentile: f64,
+    /// delay used until `min_samples` calls have been seen
+    pub initial_delay: Duration,
+    pub min_samples: usize,
+    /// send the second request to the endpoint of this region,
+    /// `tmt.<region>.tencentcloudapi.com`, instead of the nearest one with
+    /// the region of the call
+    pub region: Option<String>,
+}
+
+impl Default for HedgeConfig {
+    fn default() -> Self {
+        Self {
+            percentile: 0.95,
+            initial_delay: Duration::from_millis(500),
+            min_samples: 20,
+            region: None,
+        }
+    }
+}
+
+pub(crate) struct Hedger {
+    config: HedgeConfig,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl Hedger {
+    pub fn new(config: HedgeConfig) -> Self {
+        Self {
+            config,
+            latencies: Mutex::new(VecDeque::with_capacity(SAMPLES)),
+        }
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.config.region.as_deref()
+    }
+
+    /// how long to wait for the first request before sending the second
+    pub fn delay(&self) -> Duration {
+        let latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        if latencies.is_empty() || latencies.len() < self.config.min_samples {
+            return self.config.initial_delay;
+        }
+        let mut sorted: Vec<_> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() - 1) as f64 * self.config.percentile.clamp(0.0, 1.0);
+        sorted[rank.round() as usize]
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        if latencies.len() == SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+}
+
+/// How a [`race`] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hedged {
+    /// the first request finished within the delay
+    NotSent,
+    /// both requests were sent, the loser is cancelled in flight if
+    /// `cancelled`
+    Sent { hedge_won: bool, cancelled: bool },
+}
+
+/// Run `primary`, and `hedge` as well once `primary` takes longer than
+/// `delay`. The first success wins, an error only when both failed.
+///
+/// The loser is stopped through its token and run to its end, so it reports
+/// being cancelled rather than vanishing mid call.
+pub(crate) async fn race<T, P, H>(
+    delay: Duration,
+    (primary, stop_primary): (P, &CancellationToken),
+    (hedge, stop_hedge): (H, &CancellationToken),
+) -> (Result<T>, Hedged)
+where
+    P: Future<Output = Result<T>>,
+    H: Future<Output = Result<T>>,
+{
+    let mut primary = pin!(primary);
+    if let Ok(result) = tokio::time::timeout(delay, primary.as_mut()).await {
+        return (result, Hedged::NotSent);
+    }
+    let hedge = pin!(hedge);
+    let sent = |hedge_won, cancelled| Hedged::Sent {
+        hedge_won,
+        cancelled,
+    };
+    match select(primary, hedge).await {
+        Either::Left((Ok(v), hedge)) => {
+            stop_hedge.cancel();
+            let _ = hedge.await;
+            (Ok(v), sent(false, true))
+        }
+        Either::Right((Ok(v), primary)) => {
+            stop_primary.cancel();
+            let _ = primary.await;
+            (Ok(v), sent(true, true))
+        }
+        Either::Left((Err(err), hedge)) => match hedge.await {
+            Ok(v) => (Ok(v), sent(true, false)),
+            Err(_) => (Err(err), sent(false, false)),
+        },
+        Either::Right((Err(_), primary)) => (primary.await, sent(false, false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    /// `result` after `ms`, unless stopped by `token` first
+    async fn after<T>(ms: u64, result: Result<T>, token: &CancellationToken) -> Result<T> {
+        let sleep = tokio::time::sleep(Duration::from_millis(ms));
+        match token.run_until_cancelled(sleep).await {
+            Some(()) => result,
+            None => Err(Error::Cancelled),
+        }
+    }
+
+    #[tokio::test]
+    async fn first_success_wins() {
+        let delay = Duration::from_millis(100);
+        let (p, h) = (CancellationToken::new(), CancellationToken::new());
+        let (result, hedged) =
+            race(delay, (after(50, Ok(1), &p), &p), (after(0, Ok(2), &h), &h)).await;
+        assert_eq!((result.unwrap(), hedged), (1, Hedged::NotSent));
+
+        let (p, h) = (CancellationToken::new(), CancellationToken::new());
+        let started = std::time::Instant::now();
+        let (result, hedged) = race(
+            delay,
+            (after(5000, Ok(1), &p), &p),
+            (after(50, Ok(2), &h), &h),
+        )
+        .await;
+        let sent = Hedged::Sent {
+            hedge_won: true,
+            cancelled: true,
+        };
+        assert_eq!((result.unwrap(), hedged), (2, sent));
+        // the primary was stopped, not waited for
+        assert!(p.is_cancelled() && !h.is_cancelled());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let (p, h) = (CancellationToken::new(), CancellationToken::new());
+        let (result, _) = race(
+            delay,
+            (after(500, Ok(1), &p), &p),
+            (after(50, Err(Error::Cancelled), &h), &h),
+        )
+        .await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn delay_follows_percentile() {
+        let hedger = Hedger::new(HedgeConfig {
+            min_samples: 10,
+            percentile: 0.9,
+            ..Default::default()
+        });
+        assert_eq!(hedger.delay(), Duration::from_millis(500));
+        for ms in 1..=100 {
+            hedger.record(Duration::from_millis(ms));
+        }
+        assert_eq!(hedger.delay(), Duration::from_millis(90));
+    }
+}
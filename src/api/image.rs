@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use hyper::{
+    client::connect::Connection, header::USER_AGENT, service::Service, Body, Request, Uri,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use super::read_body;
+use crate::{Error, Result, TencentClient};
+
+/// `ImageTranslate` rejects images of this size or larger
+pub const MAX_IMAGE_SIZE: u64 = 4 << 20;
+
+/// downloads and readers are not read past this, even to be downscaled
+const MAX_SOURCE_SIZE: u64 = 16 * MAX_IMAGE_SIZE;
+
+/// Where the image given to `ImageTranslateCall` comes from
+pub enum ImageSource {
+    Path(PathBuf),
+    Bytes(Bytes),
+    /// downloaded with the client before translation
+    Url(String),
+    Reader(Box<dyn AsyncRead + Send + Unpin>),
+}
+
+impl std::fmt::Debug for ImageSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageSource::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            ImageSource::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            ImageSource::Url(url) => f.debug_tuple("Url").field(url).finish(),
+            ImageSource::Reader(_) => f.write_str("Reader"),
+        }
+    }
+}
+
+impl ImageSource {
+    /// Read the image. Without `downscale` anything over the upload limit is
+    /// rejected before it is read completely.
+    pub(crate) async fn load<S>(self, client: &TencentClient<S>, downscale: bool) -> Result<Vec<u8>>
+    where
+        S: Service<Uri> + Clone + Send + Sync + 'static,
+        S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        S::Future: Send + Unpin + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let data = match self {
+            ImageSource::Path(path) => {
+                let size = tokio::fs::metadata(&path).await?.len();
+                check_size(size, downscale)?;
+                tokio::fs::read(path).await?
+            }
+            ImageSource::Bytes(bytes) => bytes.to_vec(),
+            ImageSource::Url(url) => {
+                let request = Request::get(url.as_str())
+                    .header(USER_AGENT, client.user_agent.as_str())
+                    .body(Body::empty())
+                    .map_err(|e| Error::InvalidArgument(format!("invalid image url {url}: {e}")))?;
+                let response = client
+                    .client
+                    .request(request)
+                    .await
+                    .map_err(Error::HttpError)?;
+                if !response.status().is_success() {
+                    return Err(Error::Failure(Box::new(response)));
+                }
+                let limit = read_limit(downscale) as usize - 1;
+                read_body(response.into_body(), Some(limit))
+                    .await
+                    .map_err(|e| match e {
+                        Error::ResponseSizeLimitExceeded(size, _) => {
+                            Error::UploadSizeLimitExceeded(size, read_limit(downscale))
+                        }
+                        e => e,
+                    })?
+            }
+            ImageSource::Reader(mut reader) => {
+                // reading up to the limit is enough to reject it, the reader
+                // may never end
+                let limit = read_limit(downscale);
+                let mut data = Vec::new();
+                (&mut reader).take(limit).read_to_end(&mut data).await?;
+                if data.len() as u64 == limit {
+                    return Err(Error::UploadSizeLimitExceeded(limit, limit));
+                }
+                data
+            }
+        };
+        check_size(data.len() as u64, downscale)?;
+        if downscale && data.len() as u64 >= MAX_IMAGE_SIZE {
+            return shrink(data);
+        }
+        Ok(data)
+    }
+}
+
+/// size at which reading a source stops
+fn read_limit(downscale: bool) -> u64 {
+    if downscale {
+        MAX_SOURCE_SIZE
+    } else {
+        MAX_IMAGE_SIZE
+    }
+}
+
+fn check_size(size: u64, downscale: bool) -> Result<()> {
+    if !downscale && size >= MAX_IMAGE_SIZE {
+        return Err(Error::UploadSizeLimitExceeded(size, MAX_IMAGE_SIZE));
+    }
+    Ok(())
+}
+
+/// Re-encode as jpeg, halving the pixel count until it fits the upload limit
+#[cfg(feature = "downscale")]
+fn shrink(data: Vec<u8>) -> Result<Vec<u8>> {
+    use image::{codecs::jpeg::JpegEncoder, imageops::FilterType};
+
+    let size = data.len() as u64;
+    let invalid =
+        |e: image::ImageError| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    let mut img = image::load_from_memory(&data).map_err(invalid)?.to_rgb8();
+    loop {
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, 85)
+            .encode_image(&img)
+            .map_err(invalid)?;
+        if (out.len() as u64) < MAX_IMAGE_SIZE {
+            return Ok(out);
+        }
+        let (width, height) = (img.width() * 7 / 10, img.height() * 7 / 10);
+        if width < 64 || height < 64 {
+            return Err(Error::UploadSizeLimitExceeded(size, MAX_IMAGE_SIZE));
+        }
+        img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+    }
+}
+
+#[cfg(not(feature = "downscale"))]
+fn shrink(data: Vec<u8>) -> Result<Vec<u8>> {
+    Err(Error::UploadSizeLimitExceeded(
+        data.len() as u64,
+        MAX_IMAGE_SIZE,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+    use crate::api::mock;
+
+    #[tokio::test]
+    async fn sources_over_the_limit_stop_at_it() {
+        let client = mock::sequence(vec![]).0;
+        let endless = ImageSource::Reader(Box::new(tokio::io::repeat(0)));
+        assert!(matches!(
+            endless.load(&client, false).await,
+            Err(Error::UploadSizeLimitExceeded(
+                MAX_IMAGE_SIZE,
+                MAX_IMAGE_SIZE
+            ))
+        ));
+
+        // a download stops at the limit, whatever the server sends
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let head = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+            stream.write_all(head.as_bytes()).await.unwrap();
+            let chunk = vec![b'x'; 1 << 20];
+            loop {
+                let size = format!("{:x}\r\n", chunk.len());
+                if stream.write_all(size.as_bytes()).await.is_err()
+                    || stream.write_all(&chunk).await.is_err()
+                    || stream.write_all(b"\r\n").await.is_err()
+                {
+                    break;
+                }
+            }
+        });
+        let url = ImageSource::Url(format!("http://{addr}/image.png"));
+        assert!(matches!(
+            url.load(&client, false).await,
+            Err(Error::UploadSizeLimitExceeded(s, MAX_IMAGE_SIZE)) if s >= MAX_IMAGE_SIZE
+        ));
+
+        let small = ImageSource::Reader(Box::new(&b"png"[..]));
+        assert_eq!(small.load(&client, false).await.unwrap(), b"png");
+    }
+
+    #[cfg(feature = "downscale")]
+    #[test]
+    fn shrink_noisy_image_under_limit() {
+        // noise does not compress, 1600x1600 rgb is far over the limit as png
+        let mut seed = 1u32;
+        let img = image::RgbImage::from_fn(1600, 1600, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_le_bytes();
+            image::Rgb([r, g, b])
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        assert!(png.len() as u64 >= MAX_IMAGE_SIZE);
+
+        let jpeg = shrink(png).unwrap();
+        assert!((jpeg.len() as u64) < MAX_IMAGE_SIZE);
+        assert!(image::load_from_memory(&jpeg).is_ok());
+    }
+}
@@ -0,0 +1,248 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Language codes understood by Tencent Machine Translate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Language {
+    /// let the server detect source language, only valid as source
+    #[serde(rename = "auto")]
+    Auto,
+    /// simplified chinese
+    #[serde(rename = "zh")]
+    Zh,
+    /// traditional chinese
+    #[serde(rename = "zh-TW")]
+    ZhTw,
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "ja")]
+    Ja,
+    #[serde(rename = "ko")]
+    Ko,
+    #[serde(rename = "fr")]
+    Fr,
+    #[serde(rename = "es")]
+    Es,
+    #[serde(rename = "it")]
+    It,
+    #[serde(rename = "de")]
+    De,
+    #[serde(rename = "tr")]
+    Tr,
+    #[serde(rename = "ru")]
+    Ru,
+    #[serde(rename = "pt")]
+    Pt,
+    #[serde(rename = "vi")]
+    Vi,
+    #[serde(rename = "id")]
+    Id,
+    #[serde(rename = "th")]
+    Th,
+    #[serde(rename = "ms")]
+    Ms,
+    #[serde(rename = "ar")]
+    Ar,
+    #[serde(rename = "hi")]
+    Hi,
+}
+
+use Language::*;
+
+impl Language {
+    pub const ALL: [Language; 19] = [
+        Auto, Zh, ZhTw, En, Ja, Ko, Fr, Es, It, De, Tr, Ru, Pt, Vi, Id, Th, Ms, Ar, Hi,
+    ];
+
+    /// code sent to the api, e.g. `zh-TW`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Auto => "auto",
+            Zh => "zh",
+            ZhTw => "zh-TW",
+            En => "en",
+            Ja => "ja",
+            Ko => "ko",
+            Fr => "fr",
+            Es => "es",
+            It => "it",
+            De => "de",
+            Tr => "tr",
+            Ru => "ru",
+            Pt => "pt",
+            Vi => "vi",
+            Id => "id",
+            Th => "th",
+            Ms => "ms",
+            Ar => "ar",
+            Hi => "hi",
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returned when parsing an unknown language code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownLanguage(pub String);
+
+impl fmt::Display for UnknownLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported language code '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownLanguage {}
+
+impl FromStr for Language {
+    type Err = UnknownLanguage;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Language::ALL
+            .into_iter()
+            .find(|lang| lang.as_str() == s)
+            .ok_or_else(|| UnknownLanguage(s.to_string()))
+    }
+}
+
+/// Which translate api a language pair is checked against, every api
+/// supports a different set of source→target pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranslateKind {
+    Text,
+    File,
+    Image,
+    Speech,
+}
+
+// TextTranslate / TextTranslateBatch
+const TEXT_ZH: &[Language] = &[ZhTw, En, Ja, Ko, Fr, Es, It, De, Tr, Ru, Pt, Vi, Id, Th, Ms];
+const TEXT_ZH_TW: &[Language] = &[Zh, En, Ja, Ko, Fr, Es, It, De, Tr, Ru, Pt, Vi, Id, Th, Ms];
+const TEXT_EN: &[Language] = &[
+    Zh, ZhTw, Ja, Ko, Fr, Es, It, De, Tr, Ru, Pt, Vi, Id, Th, Ms, Ar, Hi,
+];
+const TEXT_JA: &[Language] = &[Zh, ZhTw, En, Ko];
+const TEXT_KO: &[Language] = &[Zh, ZhTw, En, Ja];
+const TEXT_FR: &[Language] = &[Zh, ZhTw, En, Es, It, De, Tr, Ru, Pt];
+const TEXT_ES: &[Language] = &[Zh, ZhTw, En, Fr, It, De, Tr, Ru, Pt];
+const TEXT_IT: &[Language] = &[Zh, ZhTw, En, Fr, Es, De, Tr, Ru, Pt];
+const TEXT_DE: &[Language] = &[Zh, ZhTw, En, Fr, Es, It, Tr, Ru, Pt];
+const TEXT_TR: &[Language] = &[Zh, ZhTw, En, Fr, Es, It, De, Ru, Pt];
+const TEXT_RU: &[Language] = &[Zh, ZhTw, En, Fr, Es, It, De, Tr, Pt];
+const TEXT_PT: &[Language] = &[Zh, ZhTw, En, Fr, Es, It, De, Tr, Ru];
+const TEXT_ZH_EN: &[Language] = &[Zh, ZhTw, En];
+const TEXT_EN_ONLY: &[Language] = &[En];
+const TEXT_AUTO: &[Language] = &[
+    Zh, ZhTw, En, Ja, Ko, Fr, Es, It, De, Tr, Ru, Pt, Vi, Id, Th, Ms, Ar, Hi,
+];
+
+// FileTranslate
+const FILE_ZH: &[Language] = &[En, Ja, Ko];
+const FILE_EN: &[Language] = &[Zh, Ja, Ko];
+const FILE_JA_KO: &[Language] = &[Zh, En];
+
+// ImageTranslate
+const IMAGE_ZH: &[Language] = &[En, Ja, Ko, Ru, Fr, De, It, Es, Pt, Ms, Th, Vi];
+const IMAGE_EN: &[Language] = &[Zh, Ja, Ko, Ru, Fr, De, It, Es, Pt, Ms, Th, Vi];
+const IMAGE_OTHER: &[Language] = &[Zh, En];
+
+// SpeechTranslate
+const SPEECH_ZH: &[Language] = &[En, Ja, Ko];
+const SPEECH_OTHER: &[Language] = &[Zh];
+
+impl TranslateKind {
+    /// All targets the api accepts for `source`, empty if `source` is not
+    /// accepted at all.
+    pub fn targets(self, source: Language) -> &'static [Language] {
+        match self {
+            TranslateKind::Text => match source {
+                Auto => TEXT_AUTO,
+                Zh => TEXT_ZH,
+                ZhTw => TEXT_ZH_TW,
+                En => TEXT_EN,
+                Ja => TEXT_JA,
+                Ko => TEXT_KO,
+                Fr => TEXT_FR,
+                Es => TEXT_ES,
+                It => TEXT_IT,
+                De => TEXT_DE,
+                Tr => TEXT_TR,
+                Ru => TEXT_RU,
+                Pt => TEXT_PT,
+                Vi | Id | Th | Ms => TEXT_ZH_EN,
+                Ar | Hi => TEXT_EN_ONLY,
+            },
+            TranslateKind::File => match source {
+                Zh => FILE_ZH,
+                En => FILE_EN,
+                Ja | Ko => FILE_JA_KO,
+                _ => &[],
+            },
+            TranslateKind::Image => match source {
+                Zh => IMAGE_ZH,
+                En => IMAGE_EN,
+                Auto | Ja | Ko | Ru | Fr | De | It | Es | Pt | Ms | Th | Vi => IMAGE_OTHER,
+                _ => &[],
+            },
+            TranslateKind::Speech => match source {
+                Zh => SPEECH_ZH,
+                En | Ja | Ko => SPEECH_OTHER,
+                _ => &[],
+            },
+        }
+    }
+
+    pub fn supports(self, source: Language, target: Language) -> bool {
+        self.targets(source).contains(&target)
+    }
+
+    /// validate language pair, used by call builders' `build()`
+    pub(crate) fn validate(
+        self,
+        source: Option<Language>,
+        target: Option<Language>,
+    ) -> std::result::Result<(), String> {
+        // missing fields are reported by builder itself
+        let (source, target) = match (source, target) {
+            (Some(s), Some(t)) => (s, t),
+            _ => return Ok(()),
+        };
+        if self.supports(source, target) {
+            return Ok(());
+        }
+        Err(format!(
+            "language pair '{source}' -> '{target}' is not supported by {self:?} translate"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_code_round_trip() {
+        for lang in Language::ALL {
+            assert_eq!(lang.as_str().parse::<Language>(), Ok(lang));
+        }
+        assert!("cn".parse::<Language>().is_err());
+        assert_eq!(serde_json::to_string(&ZhTw).unwrap(), r#""zh-TW""#);
+    }
+
+    #[test]
+    fn language_pair_table() {
+        assert!(TranslateKind::Text.supports(It, Zh));
+        assert!(TranslateKind::Text.supports(Auto, Hi));
+        assert!(!TranslateKind::Text.supports(Zh, Zh));
+        assert!(!TranslateKind::Text.supports(Zh, Auto));
+        assert!(!TranslateKind::Text.supports(Ar, Zh));
+        assert!(!TranslateKind::Speech.supports(Fr, En));
+        assert!(TranslateKind::Text.validate(Some(Ja), None).is_ok());
+        assert!(TranslateKind::Text.validate(Some(Vi), Some(Ja)).is_err());
+    }
+}
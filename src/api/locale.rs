@@ -0,0 +1,861 @@
+use std::{collections::HashMap, path::Path};
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    batch::{translate_texts, BatchArg},
+    glossary::placeholder,
+    Language, Protected, TranslateKind, TranslateMethods,
+};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+/// Format of a localization file given to [`LocaleTranslateCall`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocaleFormat {
+    /// nested json objects, string leaves are translated
+    Json,
+    /// nested yaml mappings, string leaves are translated. Comments are not kept
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// gettext `.po`, empty `msgstr` are filled from `msgid`/`msgid_plural`
+    Po,
+    /// project fluent `.ftl`
+    Fluent,
+}
+
+impl LocaleFormat {
+    /// guess format by file extension
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(LocaleFormat::Json),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(LocaleFormat::Yaml),
+            "po" | "pot" => Some(LocaleFormat::Po),
+            "ftl" => Some(LocaleFormat::Fluent),
+            _ => None,
+        }
+    }
+}
+
+/// An entry left untranslated and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocaleIssue {
+    pub key: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocaleReport {
+    /// keys translated by this call
+    pub translated: Vec<String>,
+    /// keys already translated, kept as is
+    pub skipped: Vec<String>,
+    pub untranslatable: Vec<LocaleIssue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocaleOutput {
+    /// the new locale file
+    pub content: String,
+    pub report: LocaleReport,
+}
+
+impl LocaleOutput {
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(tokio::fs::write(path, &self.content).await?)
+    }
+}
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate a json, yaml, po or fluent localization file
+    pub fn locale_translate(&self) -> LocaleTranslateCallBuilder<'a, S> {
+        LocaleTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Translate the values of a localization file with `TextTranslateBatch`.
+///
+/// Keys are never translated. Placeholders like `{name}`, `{{name}}`, `%s`,
+/// `%(name)s`, `${name}`, fluent placeables and the skeleton of icu
+/// `plural`/`select` arguments are protected. Entries whose placeholders do
+/// not survive translation keep their source text and are reported.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct LocaleTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// abort the remaining batch requests once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    format: LocaleFormat,
+    /// source locale file, for po the file to be filled
+    #[builder(setter(into))]
+    content: String,
+    /// previous version of the target locale file, its non empty values are
+    /// reused instead of translated again. Not used for po
+    #[builder(setter(into, strip_option), default)]
+    existing: Option<String>,
+}
+
+impl<'a, S> LocaleTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(self.source, self.target)
+    }
+}
+
+impl<'a, S> LocaleTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub async fn doit(self) -> Result<LocaleOutput> {
+        let existing = match self.existing {
+            Some(ref existing) => existing_values(self.format, existing)?,
+            None => HashMap::new(),
+        };
+        let (units, skipped, apply) = prepare(self.format, &self.content, &existing)?;
+        let mut report = LocaleReport {
+            skipped,
+            ..Default::default()
+        };
+
+        // units which are sent, with their index in `units`
+        let mut protected = Vec::new();
+        for (i, unit) in units.iter().enumerate() {
+            match protect_placeholders(&unit.text) {
+                Ok((p, true)) => protected.push((i, p)),
+                Ok((_, false)) => report.untranslatable.push(LocaleIssue {
+                    key: unit.key.clone(),
+                    reason: "nothing to translate".to_string(),
+                }),
+                Err(reason) => report.untranslatable.push(LocaleIssue {
+                    key: unit.key.clone(),
+                    reason,
+                }),
+            }
+        }
+        let texts = protected.iter().map(|(_, p)| p.text.clone()).collect();
+        let arg = BatchArg {
+            project_id: self.project_id,
+            source: self.source,
+            target: self.target,
+            region: &self.region,
+            cancel: self.cancel.as_ref(),
+        };
+        let translated = translate_texts(self.client, self.delegate, &arg, texts).await?;
+
+        let mut results = vec![None; units.len()];
+        for ((i, p), text) in protected.into_iter().zip(translated) {
+            let key = units[i].key.clone();
+            if p.is_intact(&text) {
+                results[i] = Some(p.restore(&text));
+                report.translated.push(key);
+            } else {
+                report.untranslatable.push(LocaleIssue {
+                    key,
+                    reason: "placeholders lost in translation".to_string(),
+                });
+            }
+        }
+        Ok(LocaleOutput {
+            content: apply(results)?,
+            report,
+        })
+    }
+}
+
+/// A value to be translated
+struct Unit {
+    key: String,
+    text: String,
+}
+
+/// writes translations, in the order of units, back into the file
+type Apply = Box<dyn FnOnce(Vec<Option<String>>) -> Result<String> + Send>;
+
+fn prepare(
+    format: LocaleFormat,
+    content: &str,
+    existing: &HashMap<String, String>,
+) -> Result<(Vec<Unit>, Vec<String>, Apply)> {
+    match format {
+        LocaleFormat::Json => {
+            let mut value: Value = serde_json::from_str(content)
+                .map_err(|e| Error::JsonError(content.to_string(), e))?;
+            let (units, skipped, plan) =
+                collect_leaves(existing, |f| visit_json(&mut value, &mut String::new(), f));
+            let apply: Apply = Box::new(move |results| {
+                write_leaves(plan, results, |f| {
+                    visit_json(&mut value, &mut String::new(), f)
+                });
+                let mut out = serde_json::to_string_pretty(&value)
+                    .map_err(|e| Error::JsonError(format!("{value:?}"), e))?;
+                out.push('\n');
+                Ok(out)
+            });
+            Ok((units, skipped, apply))
+        }
+        #[cfg(feature = "yaml")]
+        LocaleFormat::Yaml => {
+            let mut value: serde_yaml::Value = serde_yaml::from_str(content)
+                .map_err(|e| Error::InvalidArgument(format!("invalid yaml: {e}")))?;
+            let (units, skipped, plan) =
+                collect_leaves(existing, |f| visit_yaml(&mut value, &mut String::new(), f));
+            let apply: Apply = Box::new(move |results| {
+                write_leaves(plan, results, |f| {
+                    visit_yaml(&mut value, &mut String::new(), f)
+                });
+                serde_yaml::to_string(&value)
+                    .map_err(|e| Error::InvalidArgument(format!("invalid yaml: {e}")))
+            });
+            Ok((units, skipped, apply))
+        }
+        LocaleFormat::Po => Ok(prepare_po(content)),
+        LocaleFormat::Fluent => Ok(prepare_fluent(content, existing)),
+    }
+}
+
+/// key → value of an already translated target file
+fn existing_values(format: LocaleFormat, content: &str) -> Result<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    let mut collect = |key: &str, value: &mut String| {
+        if !value.trim().is_empty() {
+            values.insert(key.to_string(), value.clone());
+        }
+    };
+    match format {
+        LocaleFormat::Json => {
+            let mut value: Value = serde_json::from_str(content)
+                .map_err(|e| Error::JsonError(content.to_string(), e))?;
+            visit_json(&mut value, &mut String::new(), &mut collect);
+        }
+        #[cfg(feature = "yaml")]
+        LocaleFormat::Yaml => {
+            let mut value: serde_yaml::Value = serde_yaml::from_str(content)
+                .map_err(|e| Error::InvalidArgument(format!("invalid yaml: {e}")))?;
+            visit_yaml(&mut value, &mut String::new(), &mut collect);
+        }
+        LocaleFormat::Po => {}
+        LocaleFormat::Fluent => {
+            for line in fluent_lines(content) {
+                if let (Some(key), false) = (line.key, line.continuation) {
+                    collect(&key, &mut line.value.clone());
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+type Visitor<'f> = &'f mut dyn FnMut(&str, &mut String);
+
+fn join_key(path: &mut String, segment: &str) -> usize {
+    let len = path.len();
+    if !path.is_empty() {
+        path.push('.');
+    }
+    path.push_str(segment);
+    len
+}
+
+fn visit_json(value: &mut Value, path: &mut String, f: Visitor) {
+    match value {
+        Value::String(s) => f(path, s),
+        Value::Array(list) => {
+            for (i, item) in list.iter_mut().enumerate() {
+                let len = join_key(path, &i.to_string());
+                visit_json(item, path, f);
+                path.truncate(len);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                let len = join_key(path, key);
+                visit_json(item, path, f);
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn visit_yaml(value: &mut serde_yaml::Value, path: &mut String, f: Visitor) {
+    use serde_yaml::Value;
+    match value {
+        Value::String(s) => f(path, s),
+        Value::Sequence(list) => {
+            for (i, item) in list.iter_mut().enumerate() {
+                let len = join_key(path, &i.to_string());
+                visit_yaml(item, path, f);
+                path.truncate(len);
+            }
+        }
+        Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                let key = match key {
+                    Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .unwrap_or_default()
+                        .trim_end()
+                        .to_string(),
+                };
+                let len = join_key(path, &key);
+                visit_yaml(item, path, f);
+                path.truncate(len);
+            }
+        }
+        Value::Tagged(tagged) => visit_yaml(&mut tagged.value, path, f),
+        _ => {}
+    }
+}
+
+// first walk over string leaves: reuse existing translations, collect units
+// and remember which leaves are units
+fn collect_leaves(
+    existing: &HashMap<String, String>,
+    walk: impl FnOnce(Visitor),
+) -> (Vec<Unit>, Vec<String>, Vec<bool>) {
+    let mut units = Vec::new();
+    let mut skipped = Vec::new();
+    let mut plan = Vec::new();
+    walk(&mut |key: &str, value: &mut String| {
+        if let Some(translated) = existing.get(key) {
+            *value = translated.clone();
+            skipped.push(key.to_string());
+            plan.push(false);
+        } else if value.trim().is_empty() {
+            plan.push(false);
+        } else {
+            units.push(Unit {
+                key: key.to_string(),
+                text: value.clone(),
+            });
+            plan.push(true);
+        }
+    });
+    (units, skipped, plan)
+}
+
+// second walk over string leaves: write translations of units
+fn write_leaves(plan: Vec<bool>, results: Vec<Option<String>>, walk: impl FnOnce(Visitor)) {
+    let mut plan = plan.into_iter();
+    let mut results = results.into_iter();
+    walk(&mut |_: &str, value: &mut String| {
+        if plan.next().unwrap_or(false) {
+            if let Some(Some(translated)) = results.next() {
+                *value = translated;
+            }
+        }
+    });
+}
+
+struct PoEntry {
+    lines: Vec<String>,
+    msgid: String,
+    msgid_plural: Option<String>,
+    /// line range of the `msgstr` fields
+    msgstr_lines: Option<(usize, usize)>,
+    /// indexes of `msgstr[n]`, `None` for a plain `msgstr`
+    msgstr_index: Vec<Option<usize>>,
+    translated: bool,
+}
+
+fn prepare_po(content: &str) -> (Vec<Unit>, Vec<String>, Apply) {
+    // entries are split on blank lines, written back with the original endings
+    let crlf = content.contains("\r\n");
+    let content = content.replace("\r\n", "\n");
+    let mut entries = Vec::new();
+    let mut units = Vec::new();
+    let mut skipped = Vec::new();
+    for block in content.split("\n\n") {
+        let entry = parse_po_entry(block);
+        if entry.msgid.is_empty() || entry.msgstr_lines.is_none() {
+            // header or a block of comments
+        } else if entry.translated {
+            skipped.push(entry.msgid.clone());
+        } else {
+            units.push(Unit {
+                key: entry.msgid.clone(),
+                text: entry.msgid.clone(),
+            });
+            if let Some(ref plural) = entry.msgid_plural {
+                units.push(Unit {
+                    key: plural.clone(),
+                    text: plural.clone(),
+                });
+            }
+        }
+        entries.push(entry);
+    }
+
+    let apply: Apply = Box::new(move |results| {
+        let mut results = results.into_iter();
+        let mut blocks = Vec::with_capacity(entries.len());
+        for mut entry in entries {
+            if let (Some((start, end)), false, false) =
+                (entry.msgstr_lines, entry.msgid.is_empty(), entry.translated)
+            {
+                let singular = results.next().flatten();
+                let plural = match entry.msgid_plural {
+                    Some(_) => results.next().flatten(),
+                    None => None,
+                };
+                let msgstr = entry
+                    .msgstr_index
+                    .iter()
+                    .map(|index| match index {
+                        None => format!(
+                            "msgstr \"{}\"",
+                            po_escape(singular.as_deref().unwrap_or(""))
+                        ),
+                        Some(n) => {
+                            let text = if *n == 0 { &singular } else { &plural };
+                            format!(
+                                "msgstr[{n}] \"{}\"",
+                                po_escape(text.as_deref().unwrap_or(""))
+                            )
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                entry.lines.splice(start..end, msgstr);
+            }
+            blocks.push(entry.lines.join("\n"));
+        }
+        let out = blocks.join("\n\n");
+        Ok(if crlf { out.replace('\n', "\r\n") } else { out })
+    });
+    (units, skipped, apply)
+}
+
+fn parse_po_entry(block: &str) -> PoEntry {
+    let lines = block.split('\n').map(str::to_string).collect::<Vec<_>>();
+    let mut msgid = String::new();
+    let mut msgid_plural = None;
+    let mut msgstr_lines: Option<(usize, usize)> = None;
+    let mut msgstr_index = Vec::new();
+    let mut translated = false;
+    // field the current string continuation belongs to
+    let mut field = "";
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        let (keyword, quoted) = if line.starts_with('"') {
+            (field, line)
+        } else if let Some((keyword, quoted)) = line.split_once(' ') {
+            (keyword, quoted.trim())
+        } else {
+            continue;
+        };
+        if !quoted.starts_with('"') {
+            continue;
+        }
+        // exactly one delimiter each side, an escaped quote may end the text
+        let inner = quoted.strip_prefix('"').unwrap_or(quoted);
+        let text = po_unescape(inner.strip_suffix('"').unwrap_or(inner));
+        match keyword {
+            "msgid" => msgid.push_str(&text),
+            "msgid_plural" => msgid_plural.get_or_insert_with(String::new).push_str(&text),
+            k if k.starts_with("msgstr") => {
+                if field != k {
+                    msgstr_index.push(
+                        k.strip_prefix("msgstr[")
+                            .and_then(|n| n.trim_end_matches(']').parse().ok()),
+                    );
+                }
+                translated |= !text.is_empty();
+                let start = msgstr_lines.map_or(i, |(start, _)| start);
+                msgstr_lines = Some((start, i + 1));
+            }
+            _ => {}
+        }
+        field = keyword;
+    }
+    PoEntry {
+        lines,
+        msgid,
+        msgid_plural,
+        msgstr_lines,
+        msgstr_index,
+        translated,
+    }
+}
+
+fn po_unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn po_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+struct FluentLine {
+    /// message id, `id.attribute` for attributes, `None` for lines without text
+    key: Option<String>,
+    /// whether the line continues a multiline message
+    continuation: bool,
+    prefix: String,
+    value: String,
+}
+
+fn fluent_lines(content: &str) -> Vec<FluentLine> {
+    let mut lines = Vec::new();
+    let mut message: Option<String> = None;
+    for line in content.split_inclusive('\n') {
+        let raw = |line: &str| FluentLine {
+            key: None,
+            continuation: false,
+            prefix: line.to_string(),
+            value: String::new(),
+        };
+        let body = line.trim_end_matches(['\r', '\n']);
+        let trimmed = body.trim_start();
+        let indented = trimmed.len() != body.len();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            lines.push(raw(line));
+            continue;
+        }
+        if !indented {
+            // `id = value` or `-term = value`
+            let Some((id, _)) = body.split_once('=') else {
+                lines.push(raw(line));
+                continue;
+            };
+            message = Some(id.trim().to_string());
+        }
+        let Some(ref id) = message else {
+            lines.push(raw(line));
+            continue;
+        };
+
+        let (key, start, continuation) = if !indented {
+            let eq = body.find('=').unwrap_or_default();
+            (id.clone(), eq + 1, false)
+        } else if trimmed.starts_with('.') {
+            let eq = body.find('=').unwrap_or(body.len());
+            let attr = body[..eq].trim();
+            (format!("{id}{attr}"), (eq + 1).min(body.len()), false)
+        } else if trimmed.starts_with('[') || trimmed.starts_with("*[") {
+            // variant of a select expression
+            let bracket = body.find(']').map_or(body.len(), |i| i + 1);
+            (id.clone(), bracket, true)
+        } else {
+            (id.clone(), body.len() - trimmed.len(), true)
+        };
+        let value_start = start + (body[start..].len() - body[start..].trim_start().len());
+        let value = body[value_start..].trim_end();
+        // structure of a select expression
+        if value.is_empty() || value == "}" || value.ends_with("->") {
+            lines.push(raw(line));
+            continue;
+        }
+        lines.push(FluentLine {
+            key: Some(key),
+            continuation,
+            prefix: body[..value_start].to_string(),
+            value: value.to_string(),
+        });
+        // keep trailing spaces and line ending
+        let rest = &line[value_start + value.len()..];
+        lines.push(raw(rest));
+    }
+    lines
+}
+
+fn prepare_fluent(
+    content: &str,
+    existing: &HashMap<String, String>,
+) -> (Vec<Unit>, Vec<String>, Apply) {
+    let mut lines = fluent_lines(content);
+    let mut units = Vec::new();
+    let mut skipped = Vec::new();
+    let mut plan = Vec::with_capacity(lines.len());
+    for line in lines.iter_mut() {
+        let Some(ref key) = line.key else {
+            plan.push(false);
+            continue;
+        };
+        match existing.get(key) {
+            Some(translated) if !line.continuation => {
+                line.value = translated.clone();
+                skipped.push(key.clone());
+                plan.push(false);
+            }
+            _ => {
+                units.push(Unit {
+                    key: key.clone(),
+                    text: line.value.clone(),
+                });
+                plan.push(true);
+            }
+        }
+    }
+
+    let apply: Apply = Box::new(move |results| {
+        let mut results = results.into_iter();
+        let mut out = String::new();
+        for (line, is_unit) in lines.into_iter().zip(plan) {
+            out.push_str(&line.prefix);
+            match is_unit.then(|| results.next()).flatten().flatten() {
+                Some(translated) => out.push_str(&translated),
+                None => out.push_str(&line.value),
+            }
+        }
+        Ok(out)
+    });
+    (units, skipped, apply)
+}
+
+/// Replace placeholders with slots, returns whether there is any word left to
+/// translate.
+fn protect_placeholders(text: &str) -> std::result::Result<(Protected, bool), String> {
+    let mut out = String::with_capacity(text.len());
+    let mut slots = Vec::new();
+    let mut has_words = false;
+    // open icu `plural`/`select` variants
+    let mut depth = 0usize;
+    let mut plain = 0;
+    let mut i = 0;
+    let malformed = || format!("malformed placeholder in {text:?}");
+
+    while i < text.len() {
+        let s = &text[i..];
+        let len = if s.starts_with("{{") {
+            Some(s.find("}}").ok_or_else(malformed)? + 2)
+        } else if s.starts_with("${") {
+            Some(s.find('}').ok_or_else(malformed)? + 1)
+        } else if let Some(body) = s.strip_prefix('{') {
+            let j = body.find(['{', '}']).ok_or_else(malformed)? + 1;
+            if s[j..].starts_with('{') {
+                // `{count, plural, one {` opens the first variant
+                if !s[..j].contains(',') {
+                    return Err(malformed());
+                }
+                depth += 1;
+            }
+            Some(j + 1)
+        } else if let Some(after) = s.strip_prefix('}') {
+            if depth == 0 {
+                return Err(malformed());
+            }
+            let after = after.trim_start();
+            if after.starts_with('}') {
+                // `}}` closes the last variant and the argument
+                depth -= 1;
+                Some(s.len() - after.len() + 1)
+            } else {
+                // `} other {` switches variant
+                Some(s.find('{').ok_or_else(malformed)? + 1)
+            }
+        } else if s.starts_with('#') && depth > 0 {
+            Some(1)
+        } else if s.starts_with('%') {
+            printf_len(s)
+        } else {
+            None
+        };
+
+        match len {
+            Some(len) => {
+                has_words |= text[plain..i].chars().any(char::is_alphabetic);
+                out.push_str(&text[plain..i]);
+                out.push_str(&placeholder(slots.len()));
+                slots.push(s[..len].to_string());
+                i += len;
+                plain = i;
+            }
+            None => i += s.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    if depth > 0 {
+        return Err(malformed());
+    }
+    has_words |= text[plain..].chars().any(char::is_alphabetic);
+    out.push_str(&text[plain..]);
+    Ok((Protected::from_parts(out, slots), has_words))
+}
+
+// `%%`, `%s`, `%1$s`, `%-5.2f`, `%(name)s`
+fn printf_len(s: &str) -> Option<usize> {
+    if s.starts_with("%%") {
+        return Some(2);
+    }
+    if s.starts_with("%(") {
+        let close = s.find(')')?;
+        return s[close + 1..]
+            .chars()
+            .next()
+            .filter(char::is_ascii_alphabetic)
+            .map(|_| close + 2);
+    }
+    let spec = s[1..]
+        // the space flag is left out, "20% discount" is prose
+        .find(|c: char| !(c.is_ascii_digit() || "$-+#.".contains(c)))
+        .map(|i| i + 1)?;
+    s[spec..]
+        .chars()
+        .next()
+        .filter(|c| "sdifuxXoeEgGcp@".contains(*c))
+        .map(|_| spec + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protect_icu_and_printf() {
+        let (p, words) = protect_placeholders(
+            "Hi {name}, {count, plural, one {# file} other {# files}} %1$s %%",
+        )
+        .unwrap();
+        assert!(words);
+        assert_eq!(
+            p.text,
+            "Hi __T0__, __T1____T2__ file__T3____T4__ files__T5__ __T6__ __T7__"
+        );
+        assert_eq!(
+            p.restore(&p.text),
+            "Hi {name}, {count, plural, one {# file} other {# files}} %1$s %%"
+        );
+        assert!(!protect_placeholders("{a} %s").unwrap().1);
+        assert!(protect_placeholders("oops }").is_err());
+    }
+
+    #[test]
+    fn percent_in_prose_is_not_printf() {
+        assert_eq!(printf_len("% discount"), None);
+        for text in ["20% discount", "50% off"] {
+            assert_eq!(protect_placeholders(text).unwrap().0.text, text);
+        }
+        assert_eq!(printf_len("%-5.2f"), Some(6));
+    }
+
+    #[test]
+    fn json_keeps_keys() {
+        let mut existing = HashMap::new();
+        existing.insert("menu.open".to_string(), "打开".to_string());
+        let (units, skipped, apply) = prepare(
+            LocaleFormat::Json,
+            r#"{"menu":{"open":"Open","close":"Close","count":3},"list":["a b"]}"#,
+            &existing,
+        )
+        .unwrap();
+        assert_eq!(skipped, vec!["menu.open"]);
+        let keys = units.iter().map(|u| u.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["menu.close", "list.0"]);
+        let out = apply(vec![Some("关闭".to_string()), None]).unwrap();
+        let value: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"menu":{"open":"打开","close":"关闭","count":3},"list":["a b"]})
+        );
+    }
+
+    #[test]
+    fn po_fills_msgstr() {
+        let po = "msgid \"\"\nmsgstr \"Language: zh\\n\"\n\n#: a.c:1\nmsgid \"Hello\"\nmsgstr \"\"\n\nmsgid \"done\"\nmsgstr \"完成\"\n\nmsgid \"%d file\"\nmsgid_plural \"%d files\"\nmsgstr[0] \"\"\nmsgstr[1] \"\"";
+        let (units, skipped, apply) = prepare(LocaleFormat::Po, po, &HashMap::new()).unwrap();
+        assert_eq!(skipped, vec!["done"]);
+        assert_eq!(units.len(), 3);
+        let out = apply(vec![
+            Some("你好".to_string()),
+            Some("%d 个文件".to_string()),
+            Some("%d 个文件".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(
+            out,
+            "msgid \"\"\nmsgstr \"Language: zh\\n\"\n\n#: a.c:1\nmsgid \"Hello\"\nmsgstr \"你好\"\n\nmsgid \"done\"\nmsgstr \"完成\"\n\nmsgid \"%d file\"\nmsgid_plural \"%d files\"\nmsgstr[0] \"%d 个文件\"\nmsgstr[1] \"%d 个文件\""
+        );
+    }
+
+    #[test]
+    fn po_escaped_quotes_and_crlf() {
+        let po = "msgid \"say \\\"hi\\\"\"\r\nmsgstr \"\"\r\n\r\nmsgid \"bye\"\r\nmsgstr \"\"\r\n";
+        let (units, _, apply) = prepare(LocaleFormat::Po, po, &HashMap::new()).unwrap();
+        let texts = units.iter().map(|u| u.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["say \"hi\"", "bye"]);
+        let out = apply(vec![Some("说\"嗨\"".to_string()), None]).unwrap();
+        assert_eq!(
+            out,
+            "msgid \"say \\\"hi\\\"\"\r\nmsgstr \"说\\\"嗨\\\"\"\r\n\r\nmsgid \"bye\"\r\nmsgstr \"\"\r\n"
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_keeps_keys() {
+        let mut existing = HashMap::new();
+        existing.insert("menu.open".to_string(), "打开".to_string());
+        let yaml = "menu:\n  open: Open\n  close: Close\n  count: 3\nlist:\n- a b\n";
+        let (units, skipped, apply) = prepare(LocaleFormat::Yaml, yaml, &existing).unwrap();
+        assert_eq!(skipped, vec!["menu.open"]);
+        let keys = units.iter().map(|u| u.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["menu.close", "list.0"]);
+        let out = apply(vec![Some("关闭".to_string()), Some("甲 乙".to_string())]).unwrap();
+        assert_eq!(
+            out,
+            "menu:\n  open: 打开\n  close: 关闭\n  count: 3\nlist:\n- 甲 乙\n"
+        );
+        let values = existing_values(LocaleFormat::Yaml, &out).unwrap();
+        assert_eq!(values["menu.close"], "关闭");
+    }
+
+    #[test]
+    fn fluent_values_only() {
+        let ftl = "# comment\nhello = Hello, { $name }!\nemails =\n    { $count ->\n        [one] One email\n       *[other] { $count } emails\n    }\nlogin = Log in\n    .title = Sign in now\n";
+        let (units, _, apply) = prepare(LocaleFormat::Fluent, ftl, &HashMap::new()).unwrap();
+        let keys = units.iter().map(|u| u.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["hello", "emails", "emails", "login", "login.title"]
+        );
+        assert_eq!(units[2].text, "{ $count } emails");
+        let same = units.iter().map(|u| Some(u.text.clone())).collect();
+        assert_eq!(apply(same).unwrap(), ftl);
+    }
+}
@@ -0,0 +1,609 @@
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    batch::{translate_texts, BatchArg},
+    glossary::placeholder,
+    Language, Protected, TranslateKind, TranslateMethods,
+};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+/// Markup language of a document given to [`MarkupTranslateCall`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Html,
+    Markdown,
+}
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate a html or markdown document, keeping its structure
+    pub fn markup_translate(&self) -> MarkupTranslateCallBuilder<'a, S> {
+        MarkupTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Translate the text nodes of a html or markdown document with
+/// `TextTranslateBatch`. Tags, attributes, entities, code and urls are replaced
+/// by placeholders before translation and put back afterwards.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct MarkupTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// abort the batch requests still to come, and the one running, once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    markup: Markup,
+    #[builder(setter(into))]
+    document: String,
+}
+
+impl<'a, S> MarkupTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(self.source, self.target)
+    }
+}
+
+impl<'a, S> MarkupTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// returns the translated document
+    pub async fn doit(self) -> Result<String> {
+        let pieces = match self.markup {
+            Markup::Html => html_pieces(&self.document),
+            Markup::Markdown => markdown_pieces(&self.document),
+        };
+        let texts = pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Unit(p) => Some(p.text.clone()),
+                Piece::Raw(_) => None,
+            })
+            .collect();
+        let arg = BatchArg {
+            project_id: self.project_id,
+            source: self.source,
+            target: self.target,
+            region: &self.region,
+            cancel: self.cancel.as_ref(),
+        };
+        let mut translated = translate_texts(self.client, self.delegate, &arg, texts)
+            .await?
+            .into_iter();
+
+        let mut document = String::with_capacity(self.document.len());
+        for piece in pieces {
+            match piece {
+                Piece::Raw(raw) => document.push_str(&raw),
+                Piece::Unit(p) => {
+                    let text = translated.next().unwrap_or_default();
+                    if !p.is_intact(&text) {
+                        return Err(Error::PlaceholdersLost(p.text));
+                    }
+                    document.push_str(&p.restore(&text));
+                }
+            }
+        }
+        Ok(document)
+    }
+}
+
+#[derive(Debug)]
+enum Piece {
+    /// kept as is
+    Raw(String),
+    /// translated, with protected parts as placeholders
+    Unit(Protected),
+}
+
+/// Collects one translation unit: text plus protected slots
+#[derive(Default)]
+struct UnitBuilder {
+    original: String,
+    text: String,
+    slots: Vec<String>,
+    has_words: bool,
+}
+
+impl UnitBuilder {
+    fn push_text(&mut self, text: &str) {
+        self.original.push_str(text);
+        self.text.push_str(text);
+        self.has_words |= text.chars().any(char::is_alphabetic);
+    }
+
+    fn push_raw(&mut self, raw: &str) {
+        self.original.push_str(raw);
+        self.text.push_str(&placeholder(self.slots.len()));
+        self.slots.push(raw.to_string());
+    }
+
+    /// text with bare urls and html entities protected
+    fn push_plain(&mut self, mut text: &str) {
+        while !text.is_empty() {
+            let url = ["https://", "http://"]
+                .iter()
+                .filter_map(|scheme| text.find(scheme))
+                .min();
+            let entity = entity_start(text);
+            match (url, entity) {
+                (Some(u), e) if e.is_none_or(|(e, _)| u < e) => {
+                    self.push_text(&text[..u]);
+                    let end = text[u..]
+                        .find(|c: char| c.is_whitespace() || "<>\"'()[]".contains(c))
+                        .map_or(text.len(), |i| u + i);
+                    self.push_raw(&text[u..end]);
+                    text = &text[end..];
+                }
+                (_, Some((e, end))) => {
+                    self.push_text(&text[..e]);
+                    self.push_raw(&text[e..end]);
+                    text = &text[end..];
+                }
+                _ => {
+                    self.push_text(text);
+                    text = "";
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self, pieces: &mut Vec<Piece>) {
+        let unit = std::mem::take(self);
+        if unit.original.is_empty() {
+            return;
+        }
+        if !unit.has_words {
+            push_raw_piece(pieces, unit.original);
+            return;
+        }
+        // whitespace around the unit is layout, never send it
+        let body = unit.text.trim_start();
+        let leading = &unit.text[..unit.text.len() - body.len()];
+        let body = body.trim_end();
+        let trailing = &unit.text[leading.len() + body.len()..];
+        push_raw_piece(pieces, leading.to_string());
+        pieces.push(Piece::Unit(Protected::from_parts(
+            body.to_string(),
+            unit.slots,
+        )));
+        push_raw_piece(pieces, trailing.to_string());
+    }
+}
+
+fn push_raw_piece(pieces: &mut Vec<Piece>, raw: String) {
+    if raw.is_empty() {
+        return;
+    }
+    if let Some(Piece::Raw(last)) = pieces.last_mut() {
+        last.push_str(&raw);
+    } else {
+        pieces.push(Piece::Raw(raw));
+    }
+}
+
+// `&amp;`, `&#39;`, `&#x27;` ...
+fn entity_start(text: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    while let Some(i) = text[offset..].find('&') {
+        let start = offset + i;
+        let name_len = text[start + 1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+            .unwrap_or(text.len() - start - 1);
+        if name_len > 0 && text[start + 1 + name_len..].starts_with(';') {
+            return Some((start, start + name_len + 2));
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+/// tags which start a new translation unit
+const BLOCK_TAGS: &[&str] = &[
+    "!doctype",
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "meta",
+    "nav",
+    "ol",
+    "option",
+    "p",
+    "section",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "ul",
+];
+/// tags whose content is never translated and which end a translation unit
+const OPAQUE_BLOCK_TAGS: &[&str] = &["pre", "script", "style", "textarea"];
+/// tags whose content is never translated, kept inside a translation unit
+const OPAQUE_INLINE_TAGS: &[&str] = &["code", "kbd", "samp", "var"];
+
+fn html_pieces(doc: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut unit = UnitBuilder::default();
+    let mut rest = doc;
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |i| i + 3);
+            unit.push_raw(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let first = rest.chars().next().map_or(0, char::len_utf8);
+        let is_tag = rest.starts_with('<')
+            && rest[first..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        if !is_tag {
+            let end = rest[first..].find('<').map_or(rest.len(), |i| i + first);
+            unit.push_plain(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = tag_end(rest);
+        let closing = rest.starts_with("</");
+        let name = rest[if closing { 2 } else { 1 }..end]
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let name = name.as_str();
+        let opaque_block = OPAQUE_BLOCK_TAGS.contains(&name);
+        if !closing && (opaque_block || OPAQUE_INLINE_TAGS.contains(&name)) {
+            // keep everything up to and including the closing tag
+            let close = rest[end..]
+                .to_ascii_lowercase()
+                .find(&format!("</{name}"))
+                .map_or(rest.len(), |i| tag_end(&rest[end + i..]) + end + i);
+            if opaque_block {
+                unit.flush(&mut pieces);
+                push_raw_piece(&mut pieces, rest[..close].to_string());
+            } else {
+                unit.push_raw(&rest[..close]);
+            }
+            rest = &rest[close..];
+        } else if BLOCK_TAGS.contains(&name) {
+            unit.flush(&mut pieces);
+            push_raw_piece(&mut pieces, rest[..end].to_string());
+            rest = &rest[end..];
+        } else {
+            unit.push_raw(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+    unit.flush(&mut pieces);
+    pieces
+}
+
+// byte offset just past the `>` closing the tag at the start of `s`,
+// `>` in quoted attribute values is skipped
+fn tag_end(s: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn markdown_pieces(doc: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut fence: Option<&str> = None;
+    // an indented code block starts after a blank line, blank lines within
+    // it do not end it
+    let mut after_blank = true;
+    let mut indented_code = false;
+    for line in doc.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\r', '\n']);
+        let eol = &line[body.len()..];
+        let trimmed = body.trim_start();
+        let blank = trimmed.is_empty();
+        let code_line =
+            fence.is_none() && !blank && indent_width(body) >= 4 && (after_blank || indented_code);
+        indented_code = code_line || (indented_code && blank);
+        after_blank = blank;
+
+        if code_line {
+            push_raw_piece(&mut pieces, line.to_string());
+            continue;
+        }
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            push_raw_piece(&mut pieces, line.to_string());
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            push_raw_piece(&mut pieces, line.to_string());
+            continue;
+        }
+        // link reference definition `[id]: url`
+        if trimmed.starts_with('[') && trimmed.contains("]:") {
+            push_raw_piece(&mut pieces, line.to_string());
+            continue;
+        }
+
+        let prefix = markdown_prefix(body);
+        push_raw_piece(&mut pieces, body[..prefix].to_string());
+        let mut unit = UnitBuilder::default();
+        markdown_inline(&body[prefix..], &mut unit);
+        unit.flush(&mut pieces);
+        push_raw_piece(&mut pieces, eol.to_string());
+    }
+    pieces
+}
+
+// columns of leading whitespace, tabs stop every 4 columns
+fn indent_width(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4 - width % 4,
+            _ => break,
+        }
+    }
+    width
+}
+
+// length of block markers: indentation, `>`, `#`, list bullets and task boxes
+fn markdown_prefix(line: &str) -> usize {
+    let mut i = line.len() - line.trim_start().len();
+    while line[i..].starts_with('>') {
+        i += 1;
+        i += line[i..].len() - line[i..].trim_start().len();
+    }
+    let rest = &line[i..];
+    let hashes = rest.len() - rest.trim_start_matches('#').len();
+    if (1..=6).contains(&hashes) && rest[hashes..].starts_with(' ') {
+        i += hashes + 1;
+    } else if ["- ", "* ", "+ "].iter().any(|b| rest.starts_with(b)) {
+        i += 2;
+    } else {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && (rest[digits..].starts_with(". ") || rest[digits..].starts_with(") ")) {
+            i += digits + 2;
+        }
+    }
+    for task in ["[ ] ", "[x] ", "[X] "] {
+        if line[i..].starts_with(task) {
+            i += task.len();
+        }
+    }
+    i
+}
+
+// length of the html tag, comment or autolink `s` starts with, a lone `<`
+// is prose
+fn inline_tag_len(s: &str) -> Option<usize> {
+    if s.starts_with("<!--") {
+        return s.find("-->").map(|i| i + 3);
+    }
+    let end = s.find('>')?;
+    let inner = &s[1..end];
+    let autolink = !inner.is_empty()
+        && !inner.contains(char::is_whitespace)
+        && (inner.contains("://") || inner.contains('@'));
+    if autolink {
+        return Some(end + 1);
+    }
+    let name = inner.strip_prefix('/').unwrap_or(inner);
+    let name_len = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(name.len());
+    let tag = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name[name_len..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || c == '/');
+    tag.then(|| tag_end(s))
+}
+
+fn markdown_inline(text: &str, unit: &mut UnitBuilder) {
+    let mut rest = text;
+    let mut plain = 0;
+    while plain < rest.len() {
+        let s = &rest[plain..];
+        let c = s.chars().next().expect("not empty");
+        let protected = match c {
+            '`' => {
+                let ticks = s.len() - s.trim_start_matches('`').len();
+                let fence = &s[..ticks];
+                s[ticks..]
+                    .find(fence)
+                    .map(|i| i + 2 * ticks)
+                    .or(Some(ticks))
+            }
+            '\\' => Some(1 + s[1..].chars().next().map_or(0, char::len_utf8)),
+            '!' if s.starts_with("![") => Some(2),
+            '[' | '|' => Some(1),
+            ']' if s.starts_with("](") => s.find(')').map(|i| i + 1).or(Some(1)),
+            ']' if s.starts_with("][") => s[1..].find(']').map(|i| i + 2).or(Some(1)),
+            ']' => Some(1),
+            '<' => inline_tag_len(s),
+            '*' | '~' => Some(s.len() - s.trim_start_matches(c).len()),
+            '_' => {
+                let run = s.len() - s.trim_start_matches('_').len();
+                let before = rest[..plain].chars().next_back();
+                let after = s[run..].chars().next();
+                let inside_word = before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric);
+                (!inside_word).then_some(run)
+            }
+            _ => None,
+        };
+        match protected {
+            Some(len) => {
+                unit.push_plain(&rest[..plain]);
+                unit.push_raw(&s[..len]);
+                rest = &s[len..];
+                plain = 0;
+            }
+            None => plain += c.len_utf8(),
+        }
+    }
+    unit.push_plain(rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(pieces: &[Piece]) -> Vec<&str> {
+        pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Unit(p) => Some(p.text.as_str()),
+                Piece::Raw(_) => None,
+            })
+            .collect()
+    }
+
+    // translating every unit to itself must give back the document
+    fn round_trip(pieces: &[Piece]) -> String {
+        pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Raw(raw) => raw.clone(),
+                Piece::Unit(p) => p.restore(&p.text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn html_units() {
+        let doc = "<html><body>\n  <h1 class=\"t\">Hello &amp; <b>world</b></h1>\n<pre>let a = 1;</pre><p>Run <code>cargo build</code> at https://example.com</p>\n</body></html>";
+        let pieces = html_pieces(doc);
+        assert_eq!(
+            units(&pieces),
+            vec!["Hello __T0__ __T1__world__T2__", "Run __T0__ at __T1__"]
+        );
+        assert_eq!(round_trip(&pieces), doc);
+    }
+
+    #[test]
+    fn html_units_with_multibyte_text() {
+        let doc = "<p>中文</p><p>é accentué <i>ça</i></p><p>«引用»</p>";
+        let pieces = html_pieces(doc);
+        assert_eq!(
+            units(&pieces),
+            vec!["中文", "é accentué __T0__ça__T1__", "«引用»"]
+        );
+        assert_eq!(round_trip(&pieces), doc);
+
+        let doc = "# 标题\n- 中文 **粗体** é\n";
+        let pieces = markdown_pieces(doc);
+        assert_eq!(units(&pieces), vec!["标题", "中文 __T0__粗体__T1__ é"]);
+        assert_eq!(round_trip(&pieces), doc);
+    }
+
+    #[test]
+    fn markdown_units() {
+        let doc = "# Title\n\n- [ ] read the **docs** at [site](https://a.b/c)\n```rust\nlet x = 1;\n```\n> use `snake_case_name` please\n";
+        let pieces = markdown_pieces(doc);
+        assert_eq!(
+            units(&pieces),
+            vec![
+                "Title",
+                "read the __T0__docs__T1__ at __T2__site__T3__",
+                "use __T0__ please"
+            ]
+        );
+        assert_eq!(round_trip(&pieces), doc);
+    }
+
+    #[test]
+    fn markdown_keeps_indented_code_and_translates_lone_brackets() {
+        let doc = "Run it:\n\n    cargo build --release\n\n\tlet x = 1;\nso a < b and c > d, see <https://a.b> or <br/>\n";
+        let pieces = markdown_pieces(doc);
+        assert_eq!(
+            units(&pieces),
+            vec!["Run it:", "so a < b and c > d, see __T0__ or __T1__"]
+        );
+        assert_eq!(round_trip(&pieces), doc);
+
+        // indentation continuing a paragraph is not code
+        let pieces = markdown_pieces("first line\n    second line\n");
+        assert_eq!(units(&pieces), vec!["first line", "second line"]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_before_the_first_batch() {
+        let (client, seen) = crate::api::mock::sequence(vec![""]);
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = client
+            .translate()
+            .markup_translate()
+            .cancel(token)
+            .project_id(0u32)
+            .source(Language::En)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .markup(Markup::Html)
+            .document("<p>Hello</p>")
+            .build()
+            .unwrap()
+            .doit()
+            .await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
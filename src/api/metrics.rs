@@ -0,0 +1,149 @@
+//! Metrics recorded by `doit` through the [`metrics`] facade, compiled to
+//! nothing without the `metrics` feature.
+//!
+//! Install any `metrics` recorder to export them, e.g. a prometheus exporter
+//! or the opentelemetry bridge. Every metric is labelled with the `action`:
+//!
+//! * `tencent3_requests_total`, with `result` set to `ok`, the Tencent error
+//!   code, `http_<status>`, `network` or `cancelled`
+//! * `tencent3_request_duration_seconds`, histogram of whole calls
+//!   including retries
+//! * `tencent3_retries_total`
+//! * `tencent3_errors_total`, with the Tencent error `code`
+//! * `tencent3_billable_characters_total`, source characters of successful
+//!   calls and of hedged requests cancelled in flight
+//! * `tencent3_hedges_total`, with `winner` set to `primary` or `hedge`
+//! * `tencent3_stream_errors_total`, streamed bodies failing after the call
+//!   returned
+
+use std::time::Duration;
+
+pub(crate) struct CallMetrics {
+    #[cfg(feature = "metrics")]
+    action: &'static str,
+    #[cfg(feature = "metrics")]
+    characters: u64,
+}
+
+#[cfg(feature = "metrics")]
+impl CallMetrics {
+    pub fn new(action: &'static str, payload: &str) -> Self {
+        Self {
+            action,
+            characters: billable_characters(payload),
+        }
+    }
+
+    pub fn finish(
+        &self,
+        attempts: usize,
+        status: Option<u16>,
+        body: Option<&[u8]>,
+        cancelled: bool,
+        latency: Duration,
+    ) {
+        let action = self.action;
+        let error_code = body.and_then(|body| {
+            let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+            value["Response"]["Error"]["Code"]
+                .as_str()
+                .map(str::to_string)
+        });
+        let result = match (&error_code, body, status) {
+            _ if cancelled => "cancelled".to_string(),
+            (Some(code), _, _) => code.clone(),
+            (None, Some(_), _) => "ok".to_string(),
+            (None, None, Some(status)) => format!("http_{status}"),
+            (None, None, None) => "network".to_string(),
+        };
+
+        metrics::counter!("tencent3_requests_total", "action" => action, "result" => result)
+            .increment(1);
+        metrics::histogram!("tencent3_request_duration_seconds", "action" => action)
+            .record(latency.as_secs_f64());
+        if attempts > 1 {
+            metrics::counter!("tencent3_retries_total", "action" => action)
+                .increment(attempts as u64 - 1);
+        }
+        match error_code {
+            Some(code) => {
+                metrics::counter!("tencent3_errors_total", "action" => action, "code" => code)
+                    .increment(1);
+            }
+            None if body.is_some() && self.characters > 0 => {
+                metrics::counter!("tencent3_billable_characters_total", "action" => action)
+                    .increment(self.characters);
+            }
+            None => {}
+        }
+    }
+
+    /// count a hedged call, the loser cancelled in flight counts toward quota,
+    /// its request is counted when it finishes
+    pub fn hedge(&self, hedge_won: bool, cancelled: bool) {
+        let action = self.action;
+        let winner = if hedge_won { "hedge" } else { "primary" };
+        metrics::counter!("tencent3_hedges_total", "action" => action, "winner" => winner)
+            .increment(1);
+        if cancelled {
+            metrics::counter!("tencent3_billable_characters_total", "action" => action)
+                .increment(self.characters);
+        }
+    }
+}
+
+/// count a streamed body failing to be read
+#[cfg(feature = "metrics")]
+pub(crate) fn stream_error(action: &'static str) {
+    metrics::counter!("tencent3_stream_errors_total", "action" => action).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn stream_error(_action: &'static str) {}
+
+/// characters of the texts translation is charged for
+#[cfg(feature = "metrics")]
+fn billable_characters(payload: &str) -> u64 {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(payload) else {
+        return 0;
+    };
+    let count = |v: &serde_json::Value| v.as_str().map_or(0, |s| s.chars().count() as u64);
+    let list = value["SourceTextList"]
+        .as_array()
+        .map_or(0, |list| list.iter().map(count).sum());
+    count(&value["SourceText"]) + count(&value["Text"]) + list
+}
+
+#[cfg(not(feature = "metrics"))]
+impl CallMetrics {
+    pub fn new(_action: &'static str, _payload: &str) -> Self {
+        Self {}
+    }
+
+    pub fn finish(
+        &self,
+        _attempts: usize,
+        _status: Option<u16>,
+        _body: Option<&[u8]>,
+        _cancelled: bool,
+        _latency: Duration,
+    ) {
+    }
+
+    pub fn hedge(&self, _hedge_won: bool, _cancelled: bool) {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_billable_characters() {
+        assert_eq!(billable_characters(r#"{"SourceText":"你好 world"}"#), 8);
+        assert_eq!(
+            billable_characters(r#"{"SourceTextList":["ab","cde"],"Target":"zh"}"#),
+            5
+        );
+        assert_eq!(billable_characters(r#"{"TaskId":"1"}"#), 0);
+    }
+}
@@ -0,0 +1,546 @@
+//! The request pipeline as [`tower`] services.
+//!
+//! `doit` builds an unsigned [`ApiRequest`] per attempt and sends it through
+//! the client's stack: the layers added with
+//! [`TencentClient::with_layer`](crate::TencentClient::with_layer), outermost
+//! last added, then [`Sign`] and the hyper transport. Retries decided by the
+//! [`Delegate`](crate::client::Delegate) go through the whole stack again.
+//!
+//! Sign, rate limit, timeout and cache are layers. Retries and the
+//! `tencent3.call` span stay in `doit`, as they follow the delegate or hooks
+//! of the call, which no layer sees. A layer failing an attempt, e.g. with
+//! the `Elapsed` of [`TencentClient::with_timeout`], is retried through
+//! [`Delegate::middleware_error`].
+//!
+//! [`TencentClient::with_timeout`]: crate::TencentClient::with_timeout
+//! [`Delegate::middleware_error`]: crate::client::Delegate::middleware_error
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::future::{ready, Either, Ready};
+use hyper::{
+    client::connect::Connection,
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    service::Service,
+    Body, Client, Request, Response, Uri,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::Instant,
+};
+use tower::{util::BoxCloneSyncService, Layer};
+
+use super::{
+    read_body,
+    utils::{signature_v3_with_post, SignatureV3Arg},
+};
+use crate::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An api request before signing, the body is the json payload
+pub type ApiRequest = Request<String>;
+
+pub type ApiResponse = Response<Body>;
+
+/// Extension of an [`ApiRequest`] carrying the response size limit of the
+/// client, for layers reading the body
+#[derive(Debug, Clone, Copy)]
+pub struct MaxResponseSize(pub usize);
+
+/// The type erased stack every request is sent through
+pub type ApiService = BoxCloneSyncService<ApiRequest, ApiResponse, BoxError>;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Sends requests with a hyper client
+#[derive(Clone)]
+pub struct Transport<S> {
+    client: Client<S>,
+}
+
+impl<S> Transport<S> {
+    pub fn new(client: Client<S>) -> Self {
+        Self { client }
+    }
+}
+
+impl<S> Service<ApiRequest> for Transport<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Response = ApiResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<ApiResponse, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: ApiRequest) -> Self::Future {
+        let response = self.client.request(req.map(Body::from));
+        Box::pin(async move { response.await.map_err(BoxError::from) })
+    }
+}
+
+/// Adds `X-TC-Timestamp` and the TC3-HMAC-SHA256 `Authorization` header
+#[derive(Clone)]
+pub struct SignLayer {
+    secret_id: Arc<str>,
+    secret_key: Arc<str>,
+}
+
+impl SignLayer {
+    pub fn new(secret_id: &str, secret_key: &str) -> Self {
+        Self {
+            secret_id: secret_id.into(),
+            secret_key: secret_key.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for SignLayer {
+    type Service = Sign<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Sign {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Sign<S> {
+    inner: S,
+    layer: SignLayer,
+}
+
+impl<S> Service<ApiRequest> for Sign<S>
+where
+    S: Service<ApiRequest>,
+    S::Error: From<Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<S::Response, S::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: ApiRequest) -> Self::Future {
+        let timestamp = chrono::Utc::now().timestamp();
+        let host = req.uri().host().unwrap_or_default().to_string();
+        // the service is the first label of its endpoint, e.g. `tmt`
+        let service = host.split('.').next().unwrap_or_default();
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let authorization = signature_v3_with_post(SignatureV3Arg {
+            content_type,
+            host: &host,
+            service,
+            secret_key: &self.layer.secret_key,
+            secret_id: &self.layer.secret_id,
+            request_payload: req.body(),
+            timestamp: timestamp as u64,
+        })
+        .and_then(|authorization| {
+            HeaderValue::from_str(&authorization).map_err(|e| Error::InvalidHeader(e.into()))
+        });
+        let authorization = match authorization {
+            Ok(authorization) => authorization,
+            Err(err) => return Either::Right(ready(Err(err.into()))),
+        };
+        let headers = req.headers_mut();
+        headers.insert("X-TC-Timestamp", HeaderValue::from(timestamp));
+        headers.insert(AUTHORIZATION, authorization);
+        Either::Left(self.inner.call(req))
+    }
+}
+
+/// Hands out evenly spaced start times, shared by its clones
+#[derive(Clone)]
+pub(crate) struct Pacer {
+    pace: Duration,
+    next_start: Arc<Mutex<Instant>>,
+}
+
+impl Pacer {
+    pub fn new(num: u32, per: Duration) -> Self {
+        Self {
+            pace: per / num.max(1),
+            next_start: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// reserve the next turn, wait for it with `sleep_until`
+    pub fn next(&self) -> Instant {
+        let mut next_start = self.next_start.lock().unwrap_or_else(|e| e.into_inner());
+        let start = (*next_start).max(Instant::now());
+        *next_start = start + self.pace;
+        start
+    }
+}
+
+/// Starts at most `num` requests every `per`, later requests wait their turn
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    pacer: Pacer,
+}
+
+impl RateLimitLayer {
+    pub fn new(num: u32, per: Duration) -> Self {
+        Self {
+            pacer: Pacer::new(num, per),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<ApiRequest> for RateLimit<S>
+where
+    S: Service<ApiRequest> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // readiness of inner is awaited when the turn comes
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: ApiRequest) -> Self::Future {
+        let start = self.layer.pacer.next();
+        // readiness is driven by the future, leave a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            tokio::time::sleep_until(start).await;
+            std::future::poll_fn(|cx| inner.poll_ready(cx)).await?;
+            inner.call(req).await
+        })
+    }
+}
+
+/// Caches successful responses of read only actions, keyed by action,
+/// region and payload
+#[derive(Clone)]
+pub struct CacheLayer {
+    ttl: Duration,
+    capacity: usize,
+    actions: Arc<[&'static str]>,
+    entries: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+struct CacheEntry {
+    stored: Instant,
+    response: Response<Bytes>,
+}
+
+impl CacheLayer {
+    /// caches `TextTranslate`, `TextTranslateBatch` and `LanguageDetect`
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self::with_actions(
+            ttl,
+            capacity,
+            &["TextTranslate", "TextTranslateBatch", "LanguageDetect"],
+        )
+    }
+
+    pub fn with_actions(ttl: Duration, capacity: usize, actions: &[&'static str]) -> Self {
+        Self {
+            ttl,
+            capacity,
+            actions: actions.into(),
+            entries: Default::default(),
+        }
+    }
+
+    fn key(&self, req: &ApiRequest) -> Option<String> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let action = header("X-TC-Action");
+        self.actions
+            .contains(&action)
+            .then(|| format!("{action}\n{}\n{}", header("X-TC-Region"), req.body()))
+    }
+
+    fn get(&self, key: &str) -> Option<ApiResponse> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = entries.get(key)?;
+        if entry.stored.elapsed() > self.ttl {
+            entries.remove(key);
+            return None;
+        }
+        let mut response = Response::new(Body::from(entry.response.body().clone()));
+        *response.status_mut() = entry.response.status();
+        *response.headers_mut() = entry.response.headers().clone();
+        Some(response)
+    }
+
+    fn put(&self, key: String, response: Response<Bytes>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity {
+            entries.retain(|_, entry| entry.stored.elapsed() <= self.ttl);
+        }
+        if entries.len() >= self.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let stored = Instant::now();
+        entries.insert(key, CacheEntry { stored, response });
+    }
+}
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = Cache<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Cache {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache<S> {
+    inner: S,
+    layer: CacheLayer,
+}
+
+impl<S> Service<ApiRequest> for Cache<S>
+where
+    S: Service<ApiRequest, Response = ApiResponse, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = ApiResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<ApiResponse, BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ApiRequest) -> Self::Future {
+        let key = self.layer.key(&req);
+        let limit = req.extensions().get::<MaxResponseSize>().map(|max| max.0);
+        if let Some(response) = key.as_deref().and_then(|key| self.layer.get(key)) {
+            return Box::pin(async move { Ok(response) });
+        }
+        let response = self.inner.call(req);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let response = response.await?;
+            let Some(key) = key.filter(|_| response.status().is_success()) else {
+                return Ok(response);
+            };
+            let (parts, body) = response.into_parts();
+            let body = Bytes::from(read_body(body, limit).await?);
+            // api errors come with a success status
+            let failed = serde_json::from_slice::<serde_json::Value>(&body)
+                .map_or(true, |value| value["Response"].get("Error").is_some());
+            if !failed {
+                let mut cached = Response::new(body.clone());
+                *cached.status_mut() = parts.status;
+                *cached.headers_mut() = parts.headers.clone();
+                layer.put(key, cached);
+            }
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tower::ServiceExt;
+
+    fn request() -> ApiRequest {
+        Request::post("https://tmt.tencentcloudapi.com/")
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-TC-Action", "TextTranslate")
+            .body("{}".to_string())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sign_adds_timestamp_and_authorization() {
+        let inner = tower::service_fn(|req: ApiRequest| async move {
+            let authorization = req.headers()[AUTHORIZATION].to_str().unwrap().to_string();
+            assert!(
+                authorization.starts_with("TC3-HMAC-SHA256 Credential=id/"),
+                "{authorization}"
+            );
+            assert!(
+                authorization.contains("/tmt/tc3_request"),
+                "{authorization}"
+            );
+            assert!(req.headers().contains_key("X-TC-Timestamp"));
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        });
+        let service = SignLayer::new("id", "key").layer(inner);
+        service.oneshot(request()).await.unwrap();
+
+        // a secret id not fit for a header fails the request, not the process
+        let inner = tower::service_fn(|_: ApiRequest| async {
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        });
+        let service = SignLayer::new("i\nd", "key").layer(inner);
+        let err = service.oneshot(request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidHeader(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_spaces_requests() {
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let seen = starts.clone();
+        let inner = tower::service_fn(move |_: ApiRequest| {
+            seen.lock().unwrap().push(Instant::now());
+            async { Ok::<_, BoxError>(Response::new(Body::empty())) }
+        });
+        let service = RateLimitLayer::new(2, Duration::from_millis(100)).layer(inner);
+        let calls = (0..3).map(|_| service.clone().oneshot(request()));
+        for result in futures_util::future::join_all(calls).await {
+            result.unwrap();
+        }
+        let mut starts = starts.lock().unwrap().clone();
+        starts.sort();
+        for pair in starts.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(45), "{starts:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn transport_sends_the_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            while !received.ends_with(b"{\"A\":1}") {
+                let n = stream.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            let reply = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}";
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8(received).unwrap()
+        });
+        let request = Request::post(format!("http://{addr}/"))
+            .header("X-TC-Action", "TextTranslate")
+            .body(r#"{"A":1}"#.to_string())
+            .unwrap();
+        let response = Transport::new(Client::new())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let body = body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{}");
+        let received = server.await.unwrap().to_ascii_lowercase();
+        assert!(received.starts_with("post / http/1.1"), "{received}");
+        assert!(
+            received.contains("x-tc-action: texttranslate"),
+            "{received}"
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_answers_repeated_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let inner = tower::service_fn(move |_req: ApiRequest| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                let body = r#"{"Response":{"TargetText":"hi","RequestId":"1"}}"#;
+                Ok::<_, BoxError>(Response::new(Body::from(body)))
+            }
+        });
+        let service = CacheLayer::new(Duration::from_secs(60), 8).layer(inner);
+        let request = || {
+            Request::post("https://tmt.tencentcloudapi.com/")
+                .header("X-TC-Action", "TextTranslate")
+                .body("{}".to_string())
+                .unwrap()
+        };
+        for _ in 0..2 {
+            let response = service.clone().oneshot(request()).await.unwrap();
+            let body = body::to_bytes(response.into_body()).await.unwrap();
+            assert!(body.starts_with(br#"{"Response""#));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_keeps_the_response_size_limit() {
+        let inner = tower::service_fn(|_req: ApiRequest| async {
+            let body = format!(r#"{{"Response":{{"TargetText":"{}"}}}}"#, "a".repeat(100));
+            Ok::<_, BoxError>(Response::new(Body::from(body)))
+        });
+        let service = CacheLayer::new(Duration::from_secs(60), 8).layer(inner);
+        let request = Request::post("https://tmt.tencentcloudapi.com/")
+            .header("X-TC-Action", "TextTranslate")
+            .extension(MaxResponseSize(64))
+            .body("{}".to_string())
+            .unwrap();
+        let err = service.oneshot(request).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ResponseSizeLimitExceeded(_, 64))
+        ));
+    }
+}
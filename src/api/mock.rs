@@ -0,0 +1,55 @@
+//! A client answering from a closure instead of the network, for tests
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use hyper::{client::HttpConnector, Body, Client, Response};
+use tower::{layer::layer_fn, service_fn};
+
+use super::middleware::{ApiRequest, ApiResponse, BoxError};
+use crate::{Credential, TencentClient};
+
+pub(crate) type MockClient = TencentClient<HttpConnector>;
+
+/// a client whose requests, unsigned, are answered by `answer`
+pub(crate) fn client<F, Fut>(answer: F) -> MockClient
+where
+    F: Fn(ApiRequest) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<ApiResponse, BoxError>> + Send + 'static,
+{
+    let credential = Credential {
+        id: "id".to_string(),
+        key: "key".to_string(),
+    };
+    TencentClient::new(Client::builder().build_http(), credential)
+        .with_layer(layer_fn(move |_| service_fn(answer.clone())))
+}
+
+/// actions and payloads of the requests a client was sent
+pub(crate) type Seen = Arc<Mutex<Vec<(String, String)>>>;
+
+/// a client answering with the bodies in turn, the last one repeated
+pub(crate) fn sequence(bodies: Vec<&'static str>) -> (MockClient, Seen) {
+    let requests = Seen::default();
+    let seen = requests.clone();
+    let client = client(move |req: ApiRequest| {
+        let mut seen = seen.lock().unwrap();
+        seen.push((action(&req).to_string(), req.body().clone()));
+        let body = bodies[(seen.len() - 1).min(bodies.len() - 1)];
+        async move { Ok(json(body)) }
+    });
+    (client, requests)
+}
+
+pub(crate) fn json(body: &'static str) -> ApiResponse {
+    Response::new(Body::from(body))
+}
+
+pub(crate) fn action(req: &ApiRequest) -> &str {
+    req.headers()
+        .get("X-TC-Action")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
@@ -1,6 +1,52 @@
+mod audio;
+mod backcheck;
+mod batch;
+pub(crate) mod breaker;
+#[cfg(feature = "callback")]
+mod callback;
+mod detect;
+mod document;
+mod fanout;
+mod file;
+mod glossary;
+pub(crate) mod hedge;
+mod image;
+mod language;
+mod locale;
+mod markup;
+mod metrics;
+pub mod middleware;
+#[cfg(test)]
+pub(crate) mod mock;
+#[cfg(feature = "render")]
+mod render;
+mod response;
+mod speech;
+mod subtitle;
 mod tmt;
+mod trace;
 mod utils;
 
+pub use audio::{wav_to_pcm, AudioFormat, WavSpec};
+pub use backcheck::*;
+pub use breaker::{CircuitBreakerConfig, CircuitKey, CircuitState};
+#[cfg(feature = "callback")]
+pub use callback::*;
+pub use detect::*;
+pub use document::{DocumentType, MAX_DOCUMENT_SIZE};
+pub use fanout::*;
+pub use file::*;
+pub use glossary::{Glossary, Protected};
+pub use hedge::HedgeConfig;
+pub use image::{ImageSource, MAX_IMAGE_SIZE};
+pub use language::*;
+pub use locale::*;
+pub use markup::*;
+#[cfg(feature = "render")]
+pub use render::ImageOverlay;
+pub use response::*;
+pub use speech::*;
+pub use subtitle::*;
 pub use tmt::*;
 
 const JSON_MIME: &str = "application/json";
@@ -0,0 +1,314 @@
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+
+use super::ImageRecordItem;
+use crate::{Error, Result};
+
+/// Draws the translations of an `ImageTranslate` result onto the original
+/// image: every bounding box is filled with the colour around it and the
+/// target text is fitted inside it.
+pub struct ImageOverlay {
+    font: FontArc,
+    text_color: Option<Rgba<u8>>,
+    min_font_size: f32,
+}
+
+impl ImageOverlay {
+    /// `font_data` is a ttf/otf font covering the target language
+    pub fn new(font_data: Vec<u8>) -> Result<Self> {
+        let font = FontArc::try_from_vec(font_data)
+            .map_err(|e| Error::InvalidArgument(format!("invalid font: {e}")))?;
+        Ok(Self {
+            font,
+            text_color: None,
+            min_font_size: 8.0,
+        })
+    }
+
+    /// defaults to black or white, whichever contrasts with the box background
+    pub fn text_color(mut self, rgba: [u8; 4]) -> Self {
+        self.text_color = Some(Rgba(rgba));
+        self
+    }
+
+    /// text is never drawn smaller than this, lines overflow the box instead
+    pub fn min_font_size(mut self, px: f32) -> Self {
+        self.min_font_size = px.max(1.0);
+        self
+    }
+
+    /// render onto an encoded image (png, jpeg, webp)
+    pub fn render(&self, image: &[u8], records: &[ImageRecordItem]) -> Result<RgbaImage> {
+        let mut img = image::load_from_memory(image)
+            .map_err(invalid_image)?
+            .to_rgba8();
+        for record in records {
+            self.draw_record(&mut img, record);
+        }
+        Ok(img)
+    }
+
+    /// render and save, the format follows the extension of `path`
+    pub fn render_to_path(
+        &self,
+        image: &[u8],
+        records: &[ImageRecordItem],
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let img = self.render(image, records)?;
+        let path = path.as_ref();
+        let is_jpeg = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+        // jpeg has no alpha channel
+        let result = if is_jpeg {
+            image::DynamicImage::ImageRgba8(img).to_rgb8().save(path)
+        } else {
+            img.save(path)
+        };
+        result.map_err(invalid_image)
+    }
+
+    fn draw_record(&self, img: &mut RgbaImage, record: &ImageRecordItem) {
+        let Some(rect) = Rect::clamp(record, img.width(), img.height()) else {
+            return;
+        };
+        let background = border_average(img, &rect);
+        for y in rect.top..rect.bottom {
+            for x in rect.left..rect.right {
+                img.put_pixel(x, y, background);
+            }
+        }
+
+        let color = self.text_color.unwrap_or_else(|| contrast(background));
+        let (size, lines) = self.fit(&record.target_text, &rect);
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let line_height = scaled.height() + scaled.line_gap();
+        let text_height = line_height * lines.len() as f32;
+        let mut baseline = rect.top as f32
+            + ((rect.height() as f32 - text_height) / 2.0).max(0.0)
+            + scaled.ascent();
+        for line in lines {
+            let width = line_width(&line, |c| self.advance(c, size));
+            let mut x = rect.left as f32 + ((rect.width() as f32 - width) / 2.0).max(0.0);
+            for c in line.chars() {
+                let glyph = scaled
+                    .glyph_id(c)
+                    .with_scale_and_position(size, point(x, baseline));
+                x += scaled.h_advance(glyph.id);
+                if let Some(outline) = self.font.outline_glyph(glyph) {
+                    let bounds = outline.px_bounds();
+                    outline.draw(|gx, gy, coverage| {
+                        let px = bounds.min.x as i64 + gx as i64;
+                        let py = bounds.min.y as i64 + gy as i64;
+                        if px < 0 || py < 0 || px >= img.width() as i64 || py >= img.height() as i64
+                        {
+                            return;
+                        }
+                        let pixel = img.get_pixel_mut(px as u32, py as u32);
+                        *pixel = blend(*pixel, color, coverage);
+                    });
+                }
+            }
+            baseline += line_height;
+        }
+    }
+
+    fn advance(&self, c: char, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.h_advance(scaled.glyph_id(c))
+    }
+
+    // largest font size whose wrapped lines fit the box
+    fn fit(&self, text: &str, rect: &Rect) -> (f32, Vec<String>) {
+        let line_factor = {
+            let scaled = self.font.as_scaled(PxScale::from(100.0));
+            (scaled.height() + scaled.line_gap()) / 100.0
+        };
+        fit_text(
+            text,
+            rect.width() as f32,
+            rect.height() as f32,
+            self.min_font_size,
+            line_factor,
+            |c, size| self.advance(c, size),
+        )
+    }
+}
+
+fn invalid_image(e: image::ImageError) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+struct Rect {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl Rect {
+    fn clamp(record: &ImageRecordItem, width: u32, height: u32) -> Option<Self> {
+        let clamp_x = |v: i64| v.clamp(0, width as i64) as u32;
+        let clamp_y = |v: i64| v.clamp(0, height as i64) as u32;
+        let rect = Rect {
+            left: clamp_x(record.x),
+            top: clamp_y(record.y),
+            right: clamp_x(record.x + record.width),
+            bottom: clamp_y(record.y + record.height),
+        };
+        (rect.left < rect.right && rect.top < rect.bottom).then_some(rect)
+    }
+
+    fn width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    fn height(&self) -> u32 {
+        self.bottom - self.top
+    }
+}
+
+/// average colour of the pixels just outside the box, which is usually the
+/// background the source text was printed on
+fn border_average(img: &RgbaImage, rect: &Rect) -> Rgba<u8> {
+    let mut sum = [0u64; 4];
+    let mut count = 0u64;
+    let mut add = |x: u32, y: u32| {
+        for (s, v) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+            *s += v as u64;
+        }
+        count += 1;
+    };
+    let (left, top) = (rect.left.saturating_sub(1), rect.top.saturating_sub(1));
+    let right = rect.right.min(img.width() - 1);
+    let bottom = rect.bottom.min(img.height() - 1);
+    for x in left..=right {
+        add(x, top);
+        add(x, bottom);
+    }
+    for y in top..=bottom {
+        add(left, y);
+        add(right, y);
+    }
+    Rgba(sum.map(|s| (s / count.max(1)) as u8))
+}
+
+fn contrast(background: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b, _] = background.0;
+    let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    if luma > 140.0 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+fn blend(dst: Rgba<u8>, src: Rgba<u8>, coverage: f32) -> Rgba<u8> {
+    let alpha = coverage.clamp(0.0, 1.0) * src.0[3] as f32 / 255.0;
+    let mix = |d: u8, s: u8| (d as f32 * (1.0 - alpha) + s as f32 * alpha).round() as u8;
+    Rgba([
+        mix(dst.0[0], src.0[0]),
+        mix(dst.0[1], src.0[1]),
+        mix(dst.0[2], src.0[2]),
+        dst.0[3].max((alpha * 255.0) as u8),
+    ])
+}
+
+fn line_width(line: &str, advance: impl Fn(char) -> f32) -> f32 {
+    line.chars().map(advance).sum()
+}
+
+/// Shrink from the box height down to `min_size` until the text, wrapped at
+/// spaces (or anywhere for scripts without them), fits. `line_factor` is the
+/// line height relative to the font size.
+fn fit_text(
+    text: &str,
+    width: f32,
+    height: f32,
+    min_size: f32,
+    line_factor: f32,
+    advance: impl Fn(char, f32) -> f32,
+) -> (f32, Vec<String>) {
+    let mut size = (height / line_factor).max(min_size);
+    loop {
+        let lines = wrap(text, width, |c| advance(c, size));
+        let fits = lines.len() as f32 * size * line_factor <= height
+            && lines
+                .iter()
+                .all(|line| line_width(line, |c| advance(c, size)) <= width);
+        if fits || size <= min_size {
+            return (size, lines);
+        }
+        size = (size * 0.9).max(min_size);
+    }
+}
+
+fn wrap(text: &str, width: f32, advance: impl Fn(char) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0.0;
+    // tokens are words with their trailing space, or single chars without spaces
+    let mut tokens = Vec::new();
+    for word in text.split_inclusive(' ') {
+        if word.chars().any(|c| c.is_ascii_alphanumeric()) {
+            tokens.push(word.to_string());
+        } else {
+            tokens.extend(word.chars().map(String::from));
+        }
+    }
+    for token in tokens {
+        let token_width: f32 = token.chars().map(&advance).sum();
+        if !line.is_empty() && line_width + token_width > width {
+            lines.push(std::mem::take(&mut line).trim_end().to_string());
+            line_width = 0.0;
+        }
+        line.push_str(&token);
+        line_width += token_width;
+    }
+    if !line.trim().is_empty() {
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_text_wraps_and_shrinks() {
+        // every char is as wide as the font size
+        let advance = |_: char, size: f32| size;
+        let (size, lines) = fit_text("hello world", 60.0, 20.0, 4.0, 1.0, advance);
+        assert_eq!(lines, vec!["hello", "world"]);
+        assert!(size <= 10.0 && size > 4.0);
+
+        let (_, lines) = fit_text("你好世界", 20.0, 20.0, 10.0, 1.0, advance);
+        assert_eq!(lines, vec!["你好", "世界"]);
+    }
+
+    #[test]
+    fn erase_uses_surrounding_colour() {
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([200, 10, 10, 255]));
+        img.put_pixel(5, 5, Rgba([0, 0, 0, 255]));
+        let rect = Rect::clamp(
+            &ImageRecordItem {
+                source_text: String::new(),
+                target_text: String::new(),
+                x: 4,
+                y: 4,
+                width: 3,
+                height: 3,
+            },
+            10,
+            10,
+        )
+        .unwrap();
+        assert_eq!(border_average(&img, &rect), Rgba([200, 10, 10, 255]));
+        assert_eq!(contrast(Rgba([250, 250, 250, 255])), Rgba([0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,229 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use super::Language;
+use crate::{Error, Result};
+
+/// Decode the `Response` object of a raw api response, an api level error
+/// (`Response.Error`) is returned as [`Error::BadRequest`].
+pub fn decode_response<T: DeserializeOwned>(body: &[u8]) -> Result<T> {
+    let mut value: Value = serde_json::from_slice(body)
+        .map_err(|e| Error::JsonError(String::from_utf8_lossy(body).into_owned(), e))?;
+    let response = match value.get_mut("Response") {
+        Some(response) if response.get("Error").is_none() => response.take(),
+        _ => return Err(Error::BadRequest(value)),
+    };
+    serde_json::from_value(response.clone()).map_err(|e| Error::JsonError(response.to_string(), e))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TextTranslateResponse {
+    pub request_id: String,
+    /// kept as sent, with `auto` the api may detect codes [`Language`] does
+    /// not know
+    pub source: String,
+    pub target: Language,
+    pub target_text: String,
+}
+
+impl TextTranslateResponse {
+    pub fn source_language(&self) -> Option<Language> {
+        self.source.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TextTranslateBatchResponse {
+    pub request_id: String,
+    /// kept as sent, with `auto` the api may detect codes [`Language`] does
+    /// not know
+    pub source: String,
+    pub target: Language,
+    pub target_text_list: Vec<String>,
+}
+
+impl TextTranslateBatchResponse {
+    pub fn source_language(&self) -> Option<Language> {
+        self.source.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileTranslateResponse {
+    pub request_id: String,
+    pub data: FileTranslateTask,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileTranslateTask {
+    pub task_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileTranslateDataResponse {
+    pub request_id: String,
+    pub data: FileTranslateStatus,
+}
+
+/// State of a file translate task, as returned by `GetFileTranslate`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileTranslateStatus {
+    pub task_id: String,
+    /// `init`, `wait`, `success` or `fail`
+    pub status: String,
+    /// base64 encoded translated document, once finished
+    #[serde(default)]
+    pub file_data: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// percent
+    #[serde(default)]
+    pub progress: u32,
+    /// characters billed
+    #[serde(default)]
+    pub used_amount: Option<u64>,
+}
+
+impl FileTranslateStatus {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self.status.to_ascii_lowercase().as_str(),
+            "success" | "finish"
+        )
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status.to_ascii_lowercase().as_str(), "fail" | "failed")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageTranslateResponse {
+    pub request_id: String,
+    pub session_uuid: String,
+    /// kept as sent, with `auto` the api may detect codes [`Language`] does
+    /// not know
+    pub source: String,
+    pub target: Language,
+    pub image_record: ImageRecord,
+}
+
+impl ImageTranslateResponse {
+    pub fn source_language(&self) -> Option<Language> {
+        self.source.parse().ok()
+    }
+}
+
+/// Text regions recognized in the image and their translations
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageRecord {
+    #[serde(default)]
+    pub value: Vec<ImageRecordItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageRecordItem {
+    pub source_text: String,
+    pub target_text: String,
+    /// left of the bounding box, in pixels
+    pub x: i64,
+    /// top of the bounding box, in pixels
+    pub y: i64,
+    #[serde(rename = "W")]
+    pub width: i64,
+    #[serde(rename = "H")]
+    pub height: i64,
+}
+
+/// Result of one `SpeechTranslate` fragment
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SpeechTranslateResponse {
+    pub request_id: String,
+    pub session_uuid: String,
+    /// 1 once the current sentence is completely recognized
+    #[serde(default)]
+    pub recognize_status: i64,
+    #[serde(default)]
+    pub source_text: String,
+    #[serde(default)]
+    pub target_text: String,
+    pub seq: u32,
+    /// kept as sent, with `auto` the api may detect codes [`Language`] does
+    /// not know
+    pub source: String,
+    pub target: Language,
+    /// index of the sentence the texts belong to
+    #[serde(default)]
+    pub vad_seq: u32,
+}
+
+impl SpeechTranslateResponse {
+    pub fn source_language(&self) -> Option<Language> {
+        self.source.parse().ok()
+    }
+
+    /// the texts are final for this sentence and will not be revised
+    pub fn is_sentence_end(&self) -> bool {
+        self.recognize_status == 1
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LanguageDetectResponse {
+    pub request_id: String,
+    /// kept as sent, the api may return codes [`Language`] does not know
+    pub lang: String,
+}
+
+impl LanguageDetectResponse {
+    pub fn language(&self) -> Option<Language> {
+        self.lang.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_detected_source() {
+        let body =
+            r#"{"Response":{"RequestId":"1","Source":"xx","Target":"zh","TargetText":"嗨"}}"#;
+        let response: TextTranslateResponse = decode_response(body.as_bytes()).unwrap();
+        assert_eq!(response.source, "xx");
+        assert_eq!(response.source_language(), None);
+        assert_eq!(response.target, Language::Zh);
+    }
+
+    #[test]
+    fn file_status() {
+        let status = |s: &str| FileTranslateStatus {
+            task_id: "t1".to_string(),
+            status: s.to_string(),
+            file_data: None,
+            message: None,
+            progress: 0,
+            used_amount: None,
+        };
+        for s in ["success", "Success", "FINISH"] {
+            assert!(status(s).is_success() && !status(s).is_failed(), "{s}");
+        }
+        for s in ["fail", "Failed"] {
+            assert!(status(s).is_failed() && !status(s).is_success(), "{s}");
+        }
+        for s in ["init", "wait", ""] {
+            assert!(!status(s).is_failed() && !status(s).is_success(), "{s}");
+        }
+    }
+}
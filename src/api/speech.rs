@@ -0,0 +1,357 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream, Stream, StreamExt};
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    audio::{check_wav_spec, parse_wav_header, WavHeader},
+    decode_response,
+    utils::unless_cancelled,
+    AudioFormat, Language, SpeechTranslateResponse, TranslateKind, TranslateMethods,
+};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate a stream of audio, fragment by fragment
+    pub fn speech_translate_session(&self) -> SpeechTranslateSessionBuilder<'a, S> {
+        SpeechTranslateSessionBuilder::default().client(self.client)
+    }
+}
+
+/// A `SpeechTranslate` session over a stream of audio.
+///
+/// The audio is cut into fragments sized for `audio_format`, sent in order
+/// with increasing `Seq` and the last one flagged with `IsEnd`. Every
+/// response is yielded as soon as it arrives. A wav header at the start of
+/// pcm audio is checked and stripped.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct SpeechTranslateSession<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// end the session with [`Error::Cancelled`] once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    #[builder(setter(strip_option), default)]
+    project_id: Option<u32>,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    /// generated when not set
+    #[builder(setter(into), default = "new_session_uuid()")]
+    session_uuid: String,
+    audio_format: AudioFormat,
+    /// bytes per fragment, defaults to 200ms of `audio_format`
+    #[builder(setter(strip_option), default)]
+    frame_size: Option<usize>,
+}
+
+impl<'a, S> SpeechTranslateSessionBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.frame_size == Some(Some(0)) {
+            return Err("frame_size must not be 0".to_string());
+        }
+        TranslateKind::Speech.validate(self.source, self.target)
+    }
+}
+
+fn new_session_uuid() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{nanos:x}-{:x}-{count:x}", std::process::id())
+}
+
+impl<'a, S> SpeechTranslateSession<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub fn session_uuid(&self) -> &str {
+        &self.session_uuid
+    }
+
+    /// Send `audio` and stream back the result of every fragment. The stream
+    /// ends after the last fragment or the first error.
+    pub fn translate<A, E>(
+        self,
+        audio: A,
+    ) -> impl Stream<Item = Result<SpeechTranslateResponse>> + 'a
+    where
+        A: Stream<Item = std::result::Result<Bytes, E>> + Unpin + 'a,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let size = self
+            .frame_size
+            .unwrap_or_else(|| self.audio_format.frame_size());
+        let mut frames = Frames::new(audio, size);
+        frames.wav_header = self.audio_format == AudioFormat::Pcm;
+        stream::unfold(
+            (self, frames, 0u32),
+            |(mut session, mut frames, seq)| async move {
+                if frames.done {
+                    return None;
+                }
+                let next = unless_cancelled(session.cancel.as_ref(), frames.next()).await;
+                let result = match next {
+                    Ok(Some(Ok((data, is_end)))) => session.send(seq, data, is_end).await,
+                    Ok(Some(Err(e))) | Err(e) => Err(e),
+                    Ok(None) => return None,
+                };
+                if result.is_err() {
+                    frames.done = true;
+                }
+                Some((result, (session, frames, seq + 1)))
+            },
+        )
+    }
+
+    async fn send(
+        &mut self,
+        seq: u32,
+        data: Bytes,
+        is_end: bool,
+    ) -> Result<SpeechTranslateResponse> {
+        let mut builder = self
+            .client
+            .translate()
+            .speech_translate()
+            .source(self.source)
+            .target(self.target)
+            .region(self.region.as_str())
+            .session_uuid(self.session_uuid.as_str())
+            .audio_format(self.audio_format)
+            .audio_bytes(data)
+            .seq(seq)
+            .is_end(is_end as u8)
+            .raw_pcm(true);
+        if let Some(project_id) = self.project_id {
+            builder = builder.project_id(project_id);
+        }
+        if let Some(dlg) = self.delegate.as_deref_mut() {
+            builder = builder.delegate(dlg);
+        }
+        if let Some(ref token) = self.cancel {
+            builder = builder.cancel(token.clone());
+        }
+        let body = builder.build()?.doit(|b| b).await?;
+        decode_response(&body)
+    }
+}
+
+/// Cuts a stream of bytes into fragments of `size`, holding back the last
+/// one until the stream ends so it can be flagged.
+struct Frames<A> {
+    audio: A,
+    size: usize,
+    buffer: BytesMut,
+    done: bool,
+    /// look for a wav header before the first fragment
+    wav_header: bool,
+}
+
+impl<A, E> Frames<A>
+where
+    A: Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn new(audio: A, size: usize) -> Self {
+        Self {
+            audio,
+            size,
+            buffer: BytesMut::new(),
+            done: false,
+            wav_header: false,
+        }
+    }
+
+    /// next fragment and whether it is the last one
+    async fn next(&mut self) -> Option<Result<(Bytes, bool)>> {
+        if self.done {
+            return None;
+        }
+        while self.wav_header {
+            match parse_wav_header(&self.buffer) {
+                Ok(WavHeader::Incomplete) => {}
+                Ok(WavHeader::NotWav) => self.wav_header = false,
+                Ok(WavHeader::Wav { spec, offset, .. }) => {
+                    self.wav_header = false;
+                    if let Err(e) = check_wav_spec(&spec) {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                    let _ = self.buffer.split_to(offset);
+                    break;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            if self.wav_header {
+                match self.read().await {
+                    Ok(true) => {}
+                    // too short for a header, send it as is
+                    Ok(false) => self.wav_header = false,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }
+        while self.buffer.len() <= self.size {
+            match self.read().await {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.done = true;
+                    if self.buffer.is_empty() {
+                        return None;
+                    }
+                    return Some(Ok((self.buffer.split().freeze(), true)));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok((self.buffer.split_to(self.size).freeze(), false)))
+    }
+
+    /// append the next chunk to the buffer, false at the end of the stream
+    async fn read(&mut self) -> Result<bool> {
+        match self.audio.next().await {
+            Some(Ok(bytes)) => {
+                self.buffer.extend_from_slice(&bytes);
+                Ok(true)
+            }
+            Some(Err(e)) => {
+                self.done = true;
+                Err(Error::Io(std::io::Error::other(e)))
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_flag_the_last_fragment() {
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from_static(b"abc")),
+            Ok(Bytes::from_static(b"defgh")),
+            Ok(Bytes::from_static(b"i")),
+        ];
+        let mut frames = Frames::new(stream::iter(chunks), 3);
+        let mut out = Vec::new();
+        while let Some(frame) = frames.next().await {
+            out.push(frame.unwrap());
+        }
+        assert_eq!(
+            out,
+            vec![
+                (Bytes::from_static(b"abc"), false),
+                (Bytes::from_static(b"def"), false),
+                (Bytes::from_static(b"ghi"), true),
+            ]
+        );
+
+        let empty = stream::iter(Vec::<std::io::Result<Bytes>>::new());
+        assert!(Frames::new(empty, 3).next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn frames_strip_wav_header() {
+        let mut header = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0\x80\x3e\0\0".to_vec();
+        header.extend_from_slice(b"\0\0\0\0\0\0\x10\0data\xff\xff\xff\xff");
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::copy_from_slice(&header[..10])),
+            Ok(Bytes::copy_from_slice(&header[10..])),
+            Ok(Bytes::from_static(b"pcm")),
+        ];
+        let mut frames = Frames::new(stream::iter(chunks), 6400);
+        frames.wav_header = true;
+        assert_eq!(
+            frames.next().await.unwrap().unwrap(),
+            (Bytes::from_static(b"pcm"), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn session_sends_fragments_as_cut() {
+        let (client, seen) = crate::api::mock::sequence(vec![
+            r#"{"Response":{"RequestId":"1","SessionUuid":"s","Seq":0,"Source":"zh","Target":"en"}}"#,
+        ]);
+        // raw samples, the second fragment happening to start like wav
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from_static(b"raw samples, 16b")),
+            Ok(Bytes::from_static(b"RIFF\0\0\0\0WAVEjunk")),
+        ];
+        let results = client
+            .translate()
+            .speech_translate_session()
+            .source(Language::Zh)
+            .target(Language::En)
+            .region("ap-guangzhou")
+            .audio_format(AudioFormat::Pcm)
+            .frame_size(16)
+            .build()
+            .unwrap()
+            .translate(stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(results.iter().all(Result::is_ok), "{results:?}");
+        let seen = seen.lock().unwrap();
+        let data = |i: usize| {
+            let body: serde_json::Value = serde_json::from_str(&seen[i].1).unwrap();
+            body["Data"].as_str().unwrap().to_string()
+        };
+        assert_eq!(seen.len(), 2);
+        assert_eq!(
+            data(1),
+            crate::api::utils::to_base64(b"RIFF\0\0\0\0WAVEjunk")
+        );
+    }
+
+    #[tokio::test]
+    async fn session_ends_once_cancelled() {
+        let (client, seen) = crate::api::mock::sequence(vec![""]);
+        let token = CancellationToken::new();
+        token.cancel();
+        let session = client
+            .translate()
+            .speech_translate_session()
+            .cancel(token)
+            .source(Language::Zh)
+            .target(Language::En)
+            .region("ap-guangzhou")
+            .audio_format(AudioFormat::Pcm)
+            .build()
+            .unwrap();
+        // audio which never arrives
+        let audio = stream::pending::<std::io::Result<Bytes>>();
+        let results = session.translate(audio).collect::<Vec<_>>().await;
+        assert!(
+            matches!(results[..], [Err(Error::Cancelled)]),
+            "{results:?}"
+        );
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
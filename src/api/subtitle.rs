@@ -0,0 +1,297 @@
+use std::{fmt, path::Path};
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    batch::{translate_texts, BatchArg},
+    glossary::placeholder,
+    Language, Protected, TranslateKind, TranslateMethods,
+};
+use crate::{client::Delegate, Error, Result, TencentClient};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+/// One timed cue of a subtitle file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    /// srt sequence number or vtt cue identifier
+    pub identifier: Option<String>,
+    /// timing line including vtt cue settings, e.g. `00:00:01,000 --> 00:00:02,500`
+    pub timing: String,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Block {
+    /// `WEBVTT` header, `NOTE`, `STYLE` and `REGION` blocks
+    Raw(String),
+    Cue(Cue),
+}
+
+/// A parsed srt or webvtt file, printing it with `Display` gives the file back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subtitles {
+    pub format: SubtitleFormat,
+    blocks: Vec<Block>,
+}
+
+impl Subtitles {
+    /// parse srt or webvtt, the format is told by the `WEBVTT` signature
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let format = if content.starts_with("WEBVTT") {
+            SubtitleFormat::WebVtt
+        } else {
+            SubtitleFormat::Srt
+        };
+
+        let mut blocks = Vec::new();
+        for (i, block) in content
+            .split("\n\n")
+            .map(|b| b.trim_matches('\n'))
+            .filter(|b| !b.is_empty())
+            .enumerate()
+        {
+            let is_raw = format == SubtitleFormat::WebVtt
+                && ((i == 0 && block.starts_with("WEBVTT"))
+                    || ["NOTE", "STYLE", "REGION"]
+                        .iter()
+                        .any(|kw| block.starts_with(kw)));
+            if is_raw {
+                blocks.push(Block::Raw(block.to_string()));
+                continue;
+            }
+
+            let mut lines = block.lines();
+            let first = lines.next().unwrap_or_default();
+            let (identifier, timing) = if first.contains("-->") {
+                (None, first)
+            } else {
+                (Some(first.to_string()), lines.next().unwrap_or_default())
+            };
+            if !timing.contains("-->") {
+                return Err(Error::InvalidArgument(format!(
+                    "malformed subtitle cue: {block}"
+                )));
+            }
+            blocks.push(Block::Cue(Cue {
+                identifier,
+                timing: timing.to_string(),
+                lines: lines.map(str::to_string).collect(),
+            }));
+        }
+        Ok(Self { format, blocks })
+    }
+
+    pub async fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&tokio::fs::read_to_string(path).await?)
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        Ok(tokio::fs::write(path, self.to_string()).await?)
+    }
+
+    pub fn cues(&self) -> impl Iterator<Item = &Cue> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Cue(cue) => Some(cue),
+            Block::Raw(_) => None,
+        })
+    }
+
+    pub fn cues_mut(&mut self) -> impl Iterator<Item = &mut Cue> {
+        self.blocks.iter_mut().filter_map(|block| match block {
+            Block::Cue(cue) => Some(cue),
+            Block::Raw(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for Subtitles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match block {
+                Block::Raw(raw) => writeln!(f, "{raw}")?,
+                Block::Cue(cue) => {
+                    if let Some(ref id) = cue.identifier {
+                        writeln!(f, "{id}")?;
+                    }
+                    writeln!(f, "{}", cue.timing)?;
+                    for line in &cue.lines {
+                        writeln!(f, "{line}")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// styling like `<i>`, `<c.yellow>`, `<00:01.000>` or `{\an8}` is never translated
+fn protect_styling(text: &str) -> Protected {
+    let mut out = String::with_capacity(text.len());
+    let mut slots = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['<', '{']) {
+        let close = if rest[start..].starts_with('<') {
+            '>'
+        } else {
+            '}'
+        };
+        let Some(len) = rest[start..].find(close) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&placeholder(slots.len()));
+        slots.push(rest[start..start + len + 1].to_string());
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    Protected::from_parts(out, slots)
+}
+
+impl<'a, S> TranslateMethods<'a, S> {
+    /// Create builder to help you perform the following task:
+    /// translate a srt or webvtt subtitle file
+    pub fn subtitle_translate(&self) -> SubtitleTranslateCallBuilder<'a, S> {
+        SubtitleTranslateCallBuilder::default().client(self.client)
+    }
+}
+
+/// Translate cue text with `TextTranslateBatch`, identifiers, timing and
+/// styling tags are kept. The lines of a cue are translated together and come
+/// back as a single line.
+#[derive(derive_builder::Builder)]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
+pub struct SubtitleTranslateCall<'a, S>
+where
+    S: 'a,
+{
+    client: &'a TencentClient<S>,
+    #[builder(setter(strip_option), default)]
+    delegate: Option<&'a mut dyn Delegate>,
+    /// stop translating cues once cancelled, nothing is returned
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    project_id: u32,
+    #[builder(setter(into))]
+    source: Language,
+    #[builder(setter(into))]
+    target: Language,
+    #[builder(setter(into))]
+    region: String,
+    subtitles: Subtitles,
+    /// keep the original lines and append the translation below them
+    #[builder(default)]
+    bilingual: bool,
+}
+
+impl<'a, S> SubtitleTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(self.source, self.target)
+    }
+}
+
+impl<'a, S> SubtitleTranslateCall<'a, S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// returns the translated subtitles, fails with
+    /// [`Error::PlaceholdersLost`] when the styling of a cue did not survive
+    pub async fn doit(self) -> Result<Subtitles> {
+        let mut subtitles = self.subtitles;
+        let protected = subtitles
+            .cues()
+            .map(|cue| protect_styling(&cue.lines.join(" ")))
+            .collect::<Vec<_>>();
+        let texts = protected.iter().map(|p| p.text.clone()).collect();
+        let arg = BatchArg {
+            project_id: self.project_id,
+            source: self.source,
+            target: self.target,
+            region: &self.region,
+            cancel: self.cancel.as_ref(),
+        };
+        let translated = translate_texts(self.client, self.delegate, &arg, texts).await?;
+
+        for ((cue, p), text) in subtitles.cues_mut().zip(protected).zip(translated) {
+            if cue.lines.is_empty() {
+                continue;
+            }
+            if !p.is_intact(&text) {
+                return Err(Error::PlaceholdersLost(p.text));
+            }
+            let text = p.restore(&text);
+            if self.bilingual {
+                cue.lines.push(text);
+            } else {
+                cue.lines = vec![text];
+            }
+        }
+        Ok(subtitles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_round_trip() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i>\nworld\n\n2\n00:00:03,000 --> 00:00:04,000\n{\\an8}Bye\n";
+        let subtitles = Subtitles::parse(&srt.replace('\n', "\r\n")).unwrap();
+        assert_eq!(subtitles.format, SubtitleFormat::Srt);
+        assert_eq!(subtitles.cues().count(), 2);
+        assert_eq!(subtitles.to_string(), srt);
+
+        let p = protect_styling("<i>Hello</i> world");
+        assert_eq!(p.text, "__T0__Hello__T1__ world");
+        assert_eq!(p.restore("__T0__你好__T1__ 世界"), "<i>你好</i> 世界");
+    }
+
+    #[tokio::test]
+    async fn lost_styling_fails() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i>\n";
+        let (client, _) = crate::api::mock::sequence(vec![
+            r#"{"Response":{"RequestId":"1","Source":"en","Target":"zh","TargetTextList":["你好"]}}"#,
+        ]);
+        let result = client
+            .translate()
+            .subtitle_translate()
+            .project_id(0u32)
+            .source(Language::En)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .subtitles(Subtitles::parse(srt).unwrap())
+            .build()
+            .unwrap()
+            .doit()
+            .await;
+        assert!(
+            matches!(&result, Err(Error::PlaceholdersLost(text)) if text == "__T0__Hello__T1__"),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn vtt_round_trip() {
+        let vtt = "WEBVTT\nKind: captions\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.000 align:start\n<v Bob>Hi\n";
+        let subtitles = Subtitles::parse(vtt).unwrap();
+        assert_eq!(subtitles.format, SubtitleFormat::WebVtt);
+        let cue = subtitles.cues().next().unwrap();
+        assert_eq!(cue.identifier.as_deref(), Some("intro"));
+        assert_eq!(cue.timing, "00:01.000 --> 00:02.000 align:start");
+        assert_eq!(subtitles.to_string(), vtt);
+    }
+}
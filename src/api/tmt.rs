@@ -1,29 +1,43 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Instant};
 
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt};
 use hyper::{
-    body::{self, Buf},
+    body::HttpBody,
     client::connect::Connection,
-    header::{AUTHORIZATION, CONTENT_TYPE, HOST, USER_AGENT},
-    http::request::Builder,
+    header::{CONTENT_TYPE, HOST, USER_AGENT},
     service::Service,
     Body, Method, Request, Uri,
 };
 use serde::Serialize;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
 
 use super::{
-    utils::{signature_v3_with_post, to_base64, SignatureV3Arg},
-    CallOutput, JSON_MIME,
+    audio::wav_to_pcm,
+    breaker::{is_outage, CircuitKey},
+    glossary::restore_response,
+    hedge::{race, Hedged},
+    image::ImageSource,
+    metrics::{self, CallMetrics},
+    middleware::MaxResponseSize,
+    trace::{self, CallSpan},
+    utils::to_base64,
+    AudioFormat, CallOutput, Glossary, Language, TranslateKind, JSON_MIME,
 };
 use crate::{
     client::{self, Delegate},
+    hooks::{CallContext, DelegateHooks, Hooks},
     Error, Result, TencentClient,
 };
 
 const API_VERSION: &str = "2018-03-21";
+const SERVICE: &str = "tmt";
 const BASE_URL: &str = "https://tmt.tencentcloudapi.com/";
 const BASE_HOST: &str = "tmt.tencentcloudapi.com";
-const SERVICE: &str = "tmt";
+/// bytes of a streamed body read before handing it on, any api error fits
+const STREAM_PEEK_SIZE: usize = 16 << 10;
 
 pub struct TranslateMethods<'a, S>
 where
@@ -86,6 +100,12 @@ where
     task_id: String,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// give up on the status request, and a retry waiting its turn, once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
 }
 
 #[derive(Debug, Serialize)]
@@ -114,20 +134,50 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: String::new(),
+            regional: false,
             action: "GetFileTranslate",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.getFileTranslateData",
         };
-        Ok(f(doit(arg, |b| b).await?))
+        Ok(f(doit(arg).await?))
+    }
+
+    /// like `doit`, but hands on the json response as it arrives instead of
+    /// reading it into memory, for large translated documents. A short body,
+    /// as api errors are, is read whole first and seen by hooks like with
+    /// `doit`. A read failing later on is yielded as an error, and recorded
+    /// by the `metrics` and `tracing` features as hooks are done by then.
+    pub async fn doit_stream(self) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        let payload = FileTranslateDataPayload {
+            task_id: self.task_id,
+        };
+        let request_payload = serde_json::to_string(&payload)
+            .map_err(|e| Error::JsonError(format!("{payload:?}"), e))?;
+
+        let arg = DoitArg {
+            request_payload,
+            region: String::new(),
+            regional: false,
+            action: "GetFileTranslate",
+            dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
+            client: self.client,
+            doid: "tmt.getFileTranslateData",
+        };
+        doit_stream(arg).await
     }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct FileTranslatePayload {
-    source: String,
-    target: String,
+    source: Language,
+    target: Language,
     document_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     basic_document_type: Option<String>,
@@ -142,16 +192,16 @@ struct FileTranslatePayload {
 }
 
 #[derive(derive_builder::Builder)]
-#[builder(pattern = "owned")]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct FileTranslateCall<'a, S>
 where
     S: 'a,
 {
     client: &'a TencentClient<S>,
     #[builder(setter(into))]
-    source: String,
+    source: Language,
     #[builder(setter(into))]
-    target: String,
+    target: Language,
     #[builder(setter(into))]
     document_type: String,
     #[builder(setter(into, strip_option), default)]
@@ -166,6 +216,18 @@ where
     data: Option<String>,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// stop the upload once cancelled, a task already created keeps running
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+}
+
+impl<'a, S> FileTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::File.validate(self.source, self.target)
+    }
 }
 
 impl<'a, S> FileTranslateCall<'a, S>
@@ -195,19 +257,23 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: String::new(),
+            regional: false,
             action: "FileTranslate",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.FileTranslate",
         };
 
-        Ok(f(doit(arg, |b| b).await?))
+        Ok(f(doit(arg).await?))
     }
 }
 
 // project id 1283783
 #[derive(derive_builder::Builder)]
-#[builder(pattern = "owned")]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct ImageTranslateCall<'a, S>
 where
     S: 'a,
@@ -215,27 +281,64 @@ where
     client: &'a TencentClient<S>,
     project_id: u32,
     #[builder(setter(into))]
-    source: String,
+    source: Language,
     #[builder(setter(into))]
-    target: String,
+    target: Language,
     #[builder(setter(into))]
     session_uuid: String,
     #[builder(setter(into))]
     scene: String,
-    #[builder(setter(into))]
-    image_path: PathBuf,
+    /// set with `image_path`, `image_bytes`, `image_url` or `image_reader`
+    #[builder(setter(custom))]
+    image: ImageSource,
     #[builder(setter(into))]
     region: String,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// stop waiting for the translation once cancelled, backoff included
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    /// re-encode and downscale images over [`MAX_IMAGE_SIZE`](super::MAX_IMAGE_SIZE),
+    /// needs the `downscale` feature
+    #[builder(default)]
+    downscale: bool,
+}
+
+impl<'a, S> ImageTranslateCallBuilder<'a, S> {
+    pub fn image_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.image = Some(ImageSource::Path(path.into()));
+        self
+    }
+
+    pub fn image_bytes(mut self, bytes: impl Into<Bytes>) -> Self {
+        self.image = Some(ImageSource::Bytes(bytes.into()));
+        self
+    }
+
+    pub fn image_url(mut self, url: impl Into<String>) -> Self {
+        self.image = Some(ImageSource::Url(url.into()));
+        self
+    }
+
+    pub fn image_reader(mut self, reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        self.image = Some(ImageSource::Reader(Box::new(reader)));
+        self
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Image.validate(self.source, self.target)
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ImageTranslatePayload {
     project_id: u32,
-    source: String,
-    target: String,
+    source: Language,
+    target: Language,
     session_uuid: String,
     scene: String,
     data: String,
@@ -253,15 +356,8 @@ where
         O: CallOutput,
         F: FnMut(Vec<u8>) -> O,
     {
-        let metadata = tokio::fs::metadata(self.image_path.as_path()).await?;
         // 图片大小上限为4M，建议对源图片进行一定程度压缩
-        if metadata.len() >= 4 << 20 {
-            return Err(Error::Io(std::io::Error::new(
-                std::io::ErrorKind::Unsupported,
-                "image size should be no more than 4M",
-            )));
-        }
-        let data = tokio::fs::read(self.image_path).await?;
+        let data = self.image.load(self.client, self.downscale).await?;
         let payload = ImageTranslatePayload {
             source: self.source,
             target: self.target,
@@ -276,14 +372,17 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: self.region.clone(),
+            regional: false,
             action: "ImageTranslate",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.ImageTranslate",
         };
 
-        let b = |builder: Builder| builder.header("X-TC-Region", self.region.clone());
-        Ok(f(doit(arg, b).await?))
+        Ok(f(doit(arg).await?))
     }
 }
 
@@ -296,6 +395,12 @@ where
     client: &'a TencentClient<S>,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// fail with [`Error::Cancelled`] once cancelled, even mid backoff
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
     #[builder(setter(into))]
     region: String,
     project_id: u32,
@@ -332,19 +437,22 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: self.region.clone(),
+            regional: false,
             action: "LanguageDetect",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.LanguageDetect",
         };
 
-        let b = |builder: Builder| builder.header("X-TC-Region", self.region.clone());
-        Ok(f(doit(arg, b).await?))
+        Ok(f(doit(arg).await?))
     }
 }
 
 #[derive(derive_builder::Builder)]
-#[builder(pattern = "owned")]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct SpeechTranslateCall<'a, S>
 where
     S: 'a,
@@ -353,20 +461,52 @@ where
     #[builder(setter(strip_option), default)]
     project_id: Option<u32>,
     #[builder(setter(into))]
-    source: String,
+    source: Language,
     #[builder(setter(into))]
-    target: String,
+    target: Language,
     #[builder(setter(into))]
     session_uuid: String,
-    #[builder(setter(into))]
-    audio_path: PathBuf,
+    #[builder(setter(custom))]
+    audio: SpeechAudio,
     #[builder(setter(into))]
     region: String,
-    audio_format: u32,
+    audio_format: AudioFormat,
     seq: u32,
     is_end: u8,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// drop the fragment once cancelled, along with any pending retry
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
+    /// the fragment is raw pcm already, set by a session which stripped the
+    /// wav header of its stream
+    #[builder(vis = "pub(crate)", default)]
+    raw_pcm: bool,
+}
+
+/// Where the audio fragment given to `SpeechTranslateCall` comes from
+enum SpeechAudio {
+    Path(PathBuf),
+    Bytes(Bytes),
+}
+
+impl<'a, S> SpeechTranslateCallBuilder<'a, S> {
+    pub fn audio_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audio = Some(SpeechAudio::Path(path.into()));
+        self
+    }
+
+    pub fn audio_bytes(mut self, bytes: impl Into<Bytes>) -> Self {
+        self.audio = Some(SpeechAudio::Bytes(bytes.into()));
+        self
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Speech.validate(self.source, self.target)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -374,11 +514,11 @@ where
 pub struct SpeechTranslatePayload {
     #[serde(skip_serializing_if = "Option::is_none")]
     project_id: Option<u32>,
-    source: String,
-    target: String,
+    source: Language,
+    target: Language,
     session_uuid: String,
     data: String,
-    audio_format: u32,
+    audio_format: AudioFormat,
     seq: u32,
     is_end: u8,
 }
@@ -395,15 +535,28 @@ where
         O: CallOutput,
         F: FnMut(Vec<u8>) -> O,
     {
-        let metadata = tokio::fs::metadata(self.audio_path.as_path()).await?;
-        // 暂时也认为声音大小上限为4M
-        if metadata.len() >= 4 << 20 {
-            return Err(Error::Io(std::io::Error::new(
+        let too_large = || {
+            Error::Io(std::io::Error::new(
                 std::io::ErrorKind::Unsupported,
                 "audio file size should be no more than 4M",
-            )));
-        }
-        let data = tokio::fs::read(self.audio_path).await?;
+            ))
+        };
+        let data = match self.audio {
+            SpeechAudio::Path(path) => {
+                let metadata = tokio::fs::metadata(path.as_path()).await?;
+                // 暂时也认为声音大小上限为4M
+                if metadata.len() >= 4 << 20 {
+                    return Err(too_large());
+                }
+                tokio::fs::read(path).await?
+            }
+            SpeechAudio::Bytes(bytes) if bytes.len() >= 4 << 20 => return Err(too_large()),
+            SpeechAudio::Bytes(bytes) => bytes.to_vec(),
+        };
+        let data = match self.audio_format {
+            AudioFormat::Pcm if !self.raw_pcm => wav_to_pcm(&data)?.to_vec(),
+            _ => data,
+        };
         let payload = SpeechTranslatePayload {
             source: self.source,
             target: self.target,
@@ -420,19 +573,22 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: self.region.clone(),
+            regional: false,
             action: "SpeechTranslate",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.SpeechTranslate",
         };
 
-        let b = |builder: Builder| builder.header("X-TC-Region", self.region.clone());
-        Ok(f(doit(arg, b).await?))
+        Ok(f(doit(arg).await?))
     }
 }
 
 #[derive(derive_builder::Builder)]
-#[builder(pattern = "owned")]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct TextTranslateCall<'a, S>
 where
     S: 'a,
@@ -440,28 +596,51 @@ where
     client: &'a TencentClient<S>,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// abort the call, including retries and their backoff, once cancelled
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
     project_id: u32,
     #[builder(setter(into))]
-    source: String,
+    source: Language,
     #[builder(setter(into))]
-    target: String,
+    target: Language,
     #[builder(setter(into))]
     region: String,
     #[builder(setter(into))]
     source_text: String,
     #[builder(setter(into, strip_option), default)]
     untranslated_text: Option<String>,
+    #[builder(setter(into, strip_option), default)]
+    term_repo_id_list: Option<Vec<String>>,
+    #[builder(setter(into, strip_option), default)]
+    sent_repo_id_list: Option<Vec<String>>,
+    /// terms protected client side, see [`Glossary`]
+    #[builder(setter(strip_option), default)]
+    glossary: Option<Glossary>,
+}
+
+impl<'a, S> TextTranslateCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(self.source, self.target)
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct TextTranslatePayload {
     project_id: u32,
-    source: String,
-    target: String,
+    source: Language,
+    target: Language,
     source_text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     untranslated_text: Option<String>,
+    #[serde(rename = "TermRepoIDList", skip_serializing_if = "Option::is_none")]
+    term_repo_id_list: Option<Vec<String>>,
+    #[serde(rename = "SentRepoIDList", skip_serializing_if = "Option::is_none")]
+    sent_repo_id_list: Option<Vec<String>>,
 }
 
 impl<'a, S> TextTranslateCall<'a, S>
@@ -476,32 +655,98 @@ where
         O: CallOutput,
         F: FnMut(Vec<u8>) -> O,
     {
+        let protected = self.glossary.map(|g| g.protect(&self.source_text));
         let payload = TextTranslatePayload {
             source: self.source,
             target: self.target,
             project_id: self.project_id,
-            source_text: self.source_text,
+            source_text: match protected {
+                Some(ref p) => p.text.clone(),
+                None => self.source_text,
+            },
             untranslated_text: self.untranslated_text,
+            term_repo_id_list: self.term_repo_id_list,
+            sent_repo_id_list: self.sent_repo_id_list,
         };
 
         let request_payload = serde_json::to_string(&payload)
             .map_err(|e| Error::JsonError(format!("{payload:?}"), e))?;
 
-        let arg = DoitArg {
-            request_payload,
-            action: "TextTranslate",
-            dlg: self.delegate,
-            client: self.client,
-            doid: "tmt.TextTranslate",
+        let body = match &self.client.hedger {
+            None => {
+                let arg = DoitArg {
+                    request_payload,
+                    region: self.region,
+                    regional: false,
+                    action: "TextTranslate",
+                    dlg: self.delegate,
+                    hooks: self.hooks,
+                    cancel: self.cancel,
+                    client: self.client,
+                    doid: "tmt.TextTranslate",
+                };
+                doit(arg).await?
+            }
+            Some(hedger) => {
+                // both requests report to the delegate or hooks of the call
+                let shared = self.delegate.map(DelegateHooks::new);
+                let hooks = shared.as_ref().map(|d| d as &dyn Hooks).or(self.hooks);
+                let stop = || {
+                    self.cancel
+                        .as_ref()
+                        .map_or_else(CancellationToken::new, CancellationToken::child_token)
+                };
+                let (stop_primary, stop_hedge) = (stop(), stop());
+                let arg = |region: &str, regional, cancel: &CancellationToken| DoitArg {
+                    request_payload: request_payload.clone(),
+                    region: region.to_string(),
+                    regional,
+                    action: "TextTranslate",
+                    dlg: None,
+                    hooks,
+                    cancel: Some(cancel.clone()),
+                    client: self.client,
+                    doid: "tmt.TextTranslate",
+                };
+                let metrics = CallMetrics::new("TextTranslate", &request_payload);
+                let started = Instant::now();
+                // the delay follows the primary, a winning hedge tells it took
+                // at least as long
+                let timed = |arg| async move {
+                    let result = doit(arg).await;
+                    if result.is_ok() {
+                        hedger.record(started.elapsed());
+                    }
+                    result
+                };
+                let primary = timed(arg(&self.region, false, &stop_primary));
+                let hedge_region = hedger.region().unwrap_or(&self.region);
+                let hedge = timed(arg(hedge_region, true, &stop_hedge));
+                let (result, hedged) = race(
+                    hedger.delay(),
+                    (primary, &stop_primary),
+                    (hedge, &stop_hedge),
+                )
+                .await;
+                if let Hedged::Sent {
+                    hedge_won,
+                    cancelled,
+                } = hedged
+                {
+                    metrics.hedge(hedge_won, cancelled);
+                }
+                result?
+            }
         };
-
-        let b = |builder: Builder| builder.header("X-TC-Region", self.region.clone());
-        Ok(f(doit(arg, b).await?))
+        match protected {
+            Some(p) => Ok(f(restore_response(body, &[p])?)),
+            None => Ok(f(body)),
+        }
     }
 }
 
 #[derive(derive_builder::Builder)]
-#[builder(pattern = "owned")]
+#[builder(pattern = "owned", build_fn(validate = "Self::validate"))]
 pub struct TextTranslateBatchCall<'a, S>
 where
     S: 'a,
@@ -509,24 +754,47 @@ where
     client: &'a TencentClient<S>,
     #[builder(setter(strip_option), default)]
     delegate: Option<&'a mut dyn Delegate>,
+    /// run instead of the hooks of the client, a delegate takes precedence
+    #[builder(setter(strip_option), default)]
+    hooks: Option<&'a dyn Hooks>,
+    /// abandon the batch once cancelled, including retries
+    #[builder(setter(strip_option), default)]
+    cancel: Option<CancellationToken>,
     project_id: u32,
     #[builder(setter(into))]
-    source: String,
+    source: Language,
     #[builder(setter(into))]
-    target: String,
+    target: Language,
     #[builder(setter(into))]
     region: String,
     #[builder(setter(into))]
     source_text_list: Vec<String>,
+    #[builder(setter(into, strip_option), default)]
+    term_repo_id_list: Option<Vec<String>>,
+    #[builder(setter(into, strip_option), default)]
+    sent_repo_id_list: Option<Vec<String>>,
+    /// terms protected client side, see [`Glossary`]
+    #[builder(setter(strip_option), default)]
+    glossary: Option<Glossary>,
+}
+
+impl<'a, S> TextTranslateBatchCallBuilder<'a, S> {
+    fn validate(&self) -> std::result::Result<(), String> {
+        TranslateKind::Text.validate(self.source, self.target)
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct TextTranslateBatchPayload {
     project_id: u32,
-    source: String,
-    target: String,
+    source: Language,
+    target: Language,
     source_text_list: Vec<String>,
+    #[serde(rename = "TermRepoIDList", skip_serializing_if = "Option::is_none")]
+    term_repo_id_list: Option<Vec<String>>,
+    #[serde(rename = "SentRepoIDList", skip_serializing_if = "Option::is_none")]
+    sent_repo_id_list: Option<Vec<String>>,
 }
 
 impl<'a, S> TextTranslateBatchCall<'a, S>
@@ -541,11 +809,22 @@ where
         O: CallOutput,
         F: FnMut(Vec<u8>) -> O,
     {
+        let protected = self.glossary.map(|g| {
+            self.source_text_list
+                .iter()
+                .map(|text| g.protect(text))
+                .collect::<Vec<_>>()
+        });
         let payload = TextTranslateBatchPayload {
             source: self.source,
             target: self.target,
             project_id: self.project_id,
-            source_text_list: self.source_text_list,
+            source_text_list: match protected {
+                Some(ref list) => list.iter().map(|p| p.text.clone()).collect(),
+                None => self.source_text_list,
+            },
+            term_repo_id_list: self.term_repo_id_list,
+            sent_repo_id_list: self.sent_repo_id_list,
         };
 
         let request_payload = serde_json::to_string(&payload)
@@ -553,127 +832,837 @@ where
 
         let arg = DoitArg {
             request_payload,
+            region: self.region.clone(),
+            regional: false,
             action: "TextTranslateBatch",
             dlg: self.delegate,
+            hooks: self.hooks,
+            cancel: self.cancel,
             client: self.client,
             doid: "tmt.TextTranslateBatch",
         };
 
-        let b = |builder: Builder| builder.header("X-TC-Region", self.region.clone());
-        Ok(f(doit(arg, b).await?))
+        let body = doit(arg).await?;
+        match protected {
+            Some(list) => Ok(f(restore_response(body, &list)?)),
+            None => Ok(f(body)),
+        }
     }
 }
 
 impl CallOutput for () {}
 impl CallOutput for String {}
 impl CallOutput for Vec<String> {}
+impl CallOutput for Vec<u8> {}
+
+macro_rules! impl_from_builder_error {
+    ($($name:ident),*) => {
+        $(
+            impl From<$name> for Error {
+                fn from(err: $name) -> Self {
+                    match err {
+                        $name::UninitializedField(field) => Error::MissingField(field),
+                        $name::ValidationError(message) => Error::InvalidArgument(message),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_builder_error!(
+    FileTranslateDataCallBuilderError,
+    FileTranslateCallBuilderError,
+    ImageTranslateCallBuilderError,
+    LanguageDetectCallBuilderError,
+    SpeechTranslateCallBuilderError,
+    TextTranslateCallBuilderError,
+    TextTranslateBatchCallBuilderError
+);
 
 struct DoitArg<'a, S>
 where
     S: 'a,
 {
     request_payload: String,
+    /// empty for actions without a region
+    region: String,
+    /// send to the endpoint of `region` instead of the nearest one
+    regional: bool,
     client: &'a TencentClient<S>,
     dlg: Option<&'a mut dyn Delegate>,
+    hooks: Option<&'a dyn Hooks>,
+    cancel: Option<CancellationToken>,
     action: &'static str,
     doid: &'static str,
 }
 
-async fn doit<S, F>(arg: DoitArg<'_, S>, f: F) -> Result<Vec<u8>>
+/// a successful response, read whole or, when streaming, handed on once
+/// longer than [`STREAM_PEEK_SIZE`] with the part read so far
+enum Received {
+    Buffered(Vec<u8>),
+    Streaming(Vec<u8>, Body),
+}
+
+impl Received {
+    /// the whole body, or the start of a streamed one
+    fn body(&self) -> &[u8] {
+        match self {
+            Received::Buffered(body) | Received::Streaming(body, _) => body,
+        }
+    }
+}
+
+async fn doit<S>(arg: DoitArg<'_, S>) -> Result<Vec<u8>>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    match call(arg, false).await? {
+        Received::Buffered(body) => Ok(body),
+        Received::Streaming(..) => unreachable!("only handed on unread when streaming"),
+    }
+}
+
+/// like [`doit`], but hands on the body of a successful response unread
+async fn doit_stream<S>(
+    arg: DoitArg<'_, S>,
+) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let action = arg.action;
+    let (head, rest) = match call(arg, true).await? {
+        Received::Buffered(body) => (body, None),
+        Received::Streaming(head, body) => (head, Some(body)),
+    };
+    let head = (!head.is_empty()).then(|| Ok(Bytes::from(head)));
+    let rest = stream::unfold(rest, move |mut body| async move {
+        let chunk = body.as_mut()?.data().await?;
+        let chunk = chunk.map_err(|e| {
+            // the call has returned already, hooks will not see this
+            trace::stream_error(action, &e);
+            metrics::stream_error(action);
+            Error::HttpError(e)
+        });
+        Some((chunk, body))
+    });
+    Ok(stream::iter(head).chain(rest))
+}
+
+/// read a whole body, failing once it grows over `limit`
+pub(crate) async fn read_body(mut body: Body, limit: Option<usize>) -> Result<Vec<u8>> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let exceeded = |size: u64| Error::ResponseSizeLimitExceeded(size, limit as u64);
+    let announced = body.size_hint().lower();
+    if announced > limit as u64 {
+        return Err(exceeded(announced));
+    }
+    let mut result = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Error::HttpError)?;
+        if result.len() + chunk.len() > limit {
+            return Err(exceeded((result.len() + chunk.len()) as u64));
+        }
+        result.extend_from_slice(&chunk);
+    }
+    Ok(result)
+}
+
+async fn call<S>(arg: DoitArg<'_, S>, stream: bool) -> Result<Received>
 where
     S: Service<Uri> + Clone + Send + Sync + 'static,
     S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
     S::Future: Send + Unpin + 'static,
     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    F: Fn(Builder) -> Builder,
 {
     let DoitArg {
         request_payload,
+        region,
+        regional,
         dlg: delegate,
+        hooks,
+        cancel,
         action,
         client,
         doid,
     } = arg;
 
-    let mut dd = client::DefaultDelegate;
-    let dlg: &mut dyn client::Delegate = match delegate {
-        Some(d) => d,
-        None => &mut dd,
+    // a delegate of the call takes precedence over its hooks, those over the
+    // hooks of the client
+    let adapter;
+    let fallback;
+    let hooks: &dyn Hooks = match (delegate, hooks, &client.hooks) {
+        (Some(d), _, _) => {
+            adapter = DelegateHooks::new(d);
+            &adapter
+        }
+        (None, Some(hooks), _) => hooks,
+        (None, None, Some(hooks)) => hooks.as_ref(),
+        (None, None, None) => {
+            fallback = DelegateHooks::new(client::DefaultDelegate);
+            &fallback
+        }
     };
-    dlg.begin(client::MethodInfo {
-        id: doid,
-        http_method: Method::POST,
-    });
+    let mut cx = CallContext::new(doid, action, region.clone());
+    hooks.begin(&cx).await;
 
-    let retry_times = dlg.retry_times() as usize;
+    let span = CallSpan::new(doid, action, &region, &request_payload, client.trace_bodies);
+    let metrics = CallMetrics::new(action, &request_payload);
+    let started = Instant::now();
+    let mut status = None;
+    let key = CircuitKey {
+        service: SERVICE,
+        region: region.clone(),
+        action,
+    };
+    let permit = client.breaker.as_ref().map(|b| b.acquire(key)).transpose();
+    let result = match permit {
+        Err(err) => Err(err),
+        Ok(permit) => {
+            if let Some((permit, Some((from, to)))) = &permit {
+                hooks
+                    .circuit_state_changed(&cx, permit.key(), *from, *to)
+                    .await;
+            }
+            let send = send_with_retry(
+                client,
+                &request_payload,
+                &region,
+                regional,
+                action,
+                hooks,
+                stream,
+                &span,
+                &mut cx,
+                &mut status,
+            );
+            let send = span.instrument(send);
+            let result = match &cancel {
+                Some(token) => token.run_until_cancelled(send).await,
+                None => Some(send.await),
+            };
+            match result {
+                // a cancelled call says nothing about the health of the api
+                None => Err(Error::Cancelled),
+                Some(result) => {
+                    if let Some((permit, _)) = permit {
+                        let key = permit.key().clone();
+                        if let Some((from, to)) =
+                            permit.record(!is_outage(result.as_ref().map(Received::body)))
+                        {
+                            hooks.circuit_state_changed(&cx, &key, from, to).await;
+                        }
+                    }
+                    result
+                }
+            }
+        }
+    };
+    let body = result.as_ref().ok().map(Received::body);
+    hooks
+        .finished(&cx, result.is_ok() && cx.api_error.is_none())
+        .await;
+    span.finish(cx.attempt, status, body, started.elapsed());
+    let cancelled = matches!(result, Err(Error::Cancelled));
+    metrics.finish(cx.attempt, status, body, cancelled, started.elapsed());
+    result
+}
+
+/// `status` is set to the status of the last response, if any. With `stream`
+/// the body of a successful response is read up to [`STREAM_PEEK_SIZE`] and
+/// the rest handed on unread.
+#[allow(clippy::too_many_arguments)]
+async fn send_with_retry<S>(
+    client: &TencentClient<S>,
+    request_payload: &str,
+    region: &str,
+    regional: bool,
+    action: &'static str,
+    hooks: &dyn Hooks,
+    stream: bool,
+    span: &CallSpan,
+    cx: &mut CallContext,
+    status: &mut Option<u16>,
+) -> Result<Received>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let retry_times = hooks.retry_times() as usize;
     for i in 0..retry_times {
+        cx.attempt = i + 1;
+        *status = None;
+        let attempt = span.attempt(i + 1);
+        let attempt_started = Instant::now();
         let req_result = {
-            let https_client = &client.client;
-            let timestamp = chrono::Utc::now().timestamp();
+            let (url, host) = match regional {
+                true => (
+                    format!("https://tmt.{region}.tencentcloudapi.com/"),
+                    format!("tmt.{region}.tencentcloudapi.com"),
+                ),
+                false => (BASE_URL.to_string(), BASE_HOST.to_string()),
+            };
             let mut req_builder = Request::builder()
                 .method(Method::POST)
-                .uri(BASE_URL)
+                .uri(url)
                 .header(USER_AGENT, client.user_agent.as_str())
                 .header(CONTENT_TYPE, JSON_MIME)
-                .header(HOST, BASE_HOST)
+                .header(HOST, host)
                 .header("X-TC-Action", action)
-                .header("X-TC-Timestamp", timestamp)
                 .header("X-TC-Language", "zh-CN")
                 .header("X-TC-RequestClient", "rust-sdk")
                 .header("X-TC-Version", API_VERSION);
-            // custom construct request header
-            req_builder = f(req_builder);
-
-            let arg = SignatureV3Arg {
-                content_type: JSON_MIME,
-                host: BASE_HOST,
-                service: SERVICE,
-                secret_key: &client.credential.key,
-                secret_id: &client.credential.id,
-                request_payload: &request_payload,
-                timestamp: timestamp as u64,
-            };
-            req_builder = req_builder.header(AUTHORIZATION, signature_v3_with_post(arg));
+            if !region.is_empty() {
+                req_builder = req_builder.header("X-TC-Region", region);
+            }
+            if let Some(limit) = client.max_response_size {
+                req_builder = req_builder.extension(MaxResponseSize(limit));
+            }
 
+            // timestamp and authorization are added by the sign layer
             let request = req_builder
-                .body(Body::from(request_payload.clone()))
-                .unwrap();
-            dlg.pre_request(&request);
-            https_client.request(request).await
+                .body(request_payload.to_string())
+                .map_err(Error::InvalidHeader)?;
+            let mut preview = Request::new(Body::from(request_payload.to_string()));
+            *preview.method_mut() = request.method().clone();
+            *preview.uri_mut() = request.uri().clone();
+            *preview.headers_mut() = request.headers().clone();
+            hooks.pre_request(cx, &preview).await;
+            attempt
+                .instrument(client.service().oneshot(request))
+                .await
+                .map_err(|e| match e.downcast::<Error>() {
+                    Ok(e) => *e,
+                    Err(e) => match e.downcast::<hyper::Error>() {
+                        Ok(e) => Error::HttpError(*e),
+                        Err(e) => Error::Middleware(e),
+                    },
+                })
         };
 
         match req_result {
-            Err(err) => {
-                if let client::Retry::After(d) = dlg.http_error(&err) {
-                    // last request should not sleep
-                    if i + 1 == retry_times {
-                        break;
+            Err(Error::HttpError(err)) => {
+                attempt.finish(None, Some(&err), attempt_started.elapsed());
+                if let client::Retry::After(d) = hooks.http_error(cx, &err).await {
+                    // the last request fails with its own error
+                    if i + 1 < retry_times {
+                        tokio::time::sleep(d).await;
+                        continue;
                     }
-                    tokio::time::sleep(d).await;
-                    continue;
                 }
-                dlg.finished(false);
                 return Err(Error::HttpError(err));
             }
+            Err(Error::Middleware(err)) => {
+                attempt.finish(None, Some(&err), attempt_started.elapsed());
+                if let client::Retry::After(d) = hooks.middleware_error(cx, err.as_ref()).await {
+                    // the last request fails with its own error
+                    if i + 1 < retry_times {
+                        tokio::time::sleep(d).await;
+                        continue;
+                    }
+                }
+                return Err(Error::Middleware(err));
+            }
+            Err(err) => {
+                attempt.finish(None, Some(&err), attempt_started.elapsed());
+                return Err(err);
+            }
             Ok(res) => {
+                *status = Some(res.status().as_u16());
+                attempt.finish(*status, None, attempt_started.elapsed());
                 if !res.status().is_success() {
-                    if let client::Retry::After(d) = dlg.http_failure(&res) {
-                        // last request should not sleep
-                        if i + 1 == retry_times {
-                            break;
+                    if let client::Retry::After(d) = hooks.http_failure(cx, &res).await {
+                        // the last request fails with its own error
+                        if i + 1 < retry_times {
+                            tokio::time::sleep(d).await;
+                            continue;
                         }
-                        tokio::time::sleep(d).await;
-                        continue;
                     }
-                    dlg.finished(false);
-                    return Err(Error::Failure(res));
+                    return Err(Error::Failure(Box::new(res)));
                 }
-                let mut bytes = body::aggregate(res.into_body()).await.unwrap();
-                let mut result = vec![0; bytes.remaining()];
-                bytes.copy_to_slice(&mut result);
-                return Ok(result);
+                let (parts, mut body) = res.into_parts();
+                let mut result = Vec::new();
+                if stream {
+                    // api errors are short, a body outgrowing the peek is
+                    // taken for the document asked for
+                    while let Some(chunk) = body.data().await {
+                        result.extend_from_slice(&chunk.map_err(Error::HttpError)?);
+                        if result.len() > STREAM_PEEK_SIZE {
+                            return Ok(Received::Streaming(result, body));
+                        }
+                    }
+                } else {
+                    result = read_body(body, client.max_response_size).await?;
+                }
+                if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&result) {
+                    cx.read_response(&value);
+                    let response = client::ResponseInfo {
+                        status: parts.status,
+                        headers: &parts.headers,
+                        request_id: value["Response"]["RequestId"].as_str(),
+                        body: &value,
+                    };
+                    match hooks.post_response(cx, &response).await {
+                        client::PostResponse::Keep => {}
+                        client::PostResponse::Replace(body) => {
+                            cx.read_response(&body);
+                            result = serde_json::to_vec(&body)
+                                .map_err(|e| Error::JsonError(body.to_string(), e))?;
+                        }
+                        client::PostResponse::Reject(reason) => {
+                            return Err(Error::Rejected(reason));
+                        }
+                    }
+                }
+                return Ok(Received::Buffered(result));
             }
         }
     }
+    // no request was allowed at all
     Err(Error::Cancelled)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::api::{decode_response, mock, TextTranslateResponse};
+
+    const REPLY: &str =
+        r#"{"Response":{"RequestId":"1","Source":"en","Target":"zh","TargetText":"嗨"}}"#;
+
+    fn text_translate(
+        client: &mock::MockClient,
+    ) -> TextTranslateCallBuilder<'_, hyper::client::HttpConnector> {
+        client
+            .translate()
+            .text_translate()
+            .project_id(0u32)
+            .source(Language::En)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .source_text("hi")
+    }
+
+    #[tokio::test]
+    async fn region_header_only_for_actions_with_a_region() {
+        let regions = Arc::new(Mutex::new(Vec::new()));
+        let seen = regions.clone();
+        let client = mock::client(move |req| {
+            let region = req.headers().get("X-TC-Region").cloned();
+            seen.lock().unwrap().push(region);
+            async { Ok(mock::json(REPLY)) }
+        });
+        text_translate(&client)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        client
+            .translate()
+            .get_file_translate_data()
+            .task_id("t1")
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        let regions = regions.lock().unwrap();
+        assert_eq!(regions[0].as_ref().unwrap(), "ap-guangzhou");
+        assert_eq!(regions[1], None);
+    }
+
+    #[tokio::test]
+    async fn region_with_a_newline_is_an_invalid_header() {
+        let credential = crate::Credential {
+            id: "id".to_string(),
+            key: "key".to_string(),
+        };
+        // the whole stack, signing included, nothing is sent
+        let client = TencentClient::new(hyper::Client::builder().build_http(), credential);
+        let result = text_translate(&client)
+            .region("ap-guangzhou\n")
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await;
+        assert!(matches!(result, Err(Error::InvalidHeader(_))), "{result:?}");
+    }
+
+    /// retries every failure after the given backoff
+    struct Retrying(std::time::Duration);
+
+    impl Delegate for Retrying {
+        fn http_failure(&mut self, _: &hyper::Response<Body>) -> client::Retry {
+            client::Retry::After(self.0)
+        }
+    }
+
+    fn unavailable() -> mock::MockClient {
+        mock::client(|_| async {
+            let mut response = mock::json("");
+            *response.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+            Ok(response)
+        })
+    }
+
+    #[tokio::test]
+    async fn exhausted_retries_return_the_last_failure() {
+        let attempts = Arc::new(Mutex::new(0));
+        let seen = attempts.clone();
+        let client = mock::client(move |_| {
+            *seen.lock().unwrap() += 1;
+            async {
+                let mut response = mock::json("");
+                *response.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+                Ok(response)
+            }
+        });
+        let mut delegate = Retrying(std::time::Duration::from_millis(1));
+        let result = text_translate(&client)
+            .delegate(&mut delegate)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await;
+        assert!(
+            matches!(&result, Err(Error::Failure(res)) if res.status().as_u16() == 503),
+            "{result:?}"
+        );
+        assert_eq!(*attempts.lock().unwrap(), 3);
+    }
+
+    struct RetryingTimeouts;
+
+    impl Delegate for RetryingTimeouts {
+        fn middleware_error(
+            &mut self,
+            err: &(dyn std::error::Error + Send + Sync + 'static),
+        ) -> client::Retry {
+            assert!(err.is::<tower::timeout::error::Elapsed>());
+            client::Retry::After(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[tokio::test]
+    async fn timed_out_attempts_are_retried() {
+        let attempts = Arc::new(Mutex::new(0));
+        let seen = attempts.clone();
+        let client = mock::client(move |_| {
+            let attempt = {
+                let mut attempts = seen.lock().unwrap();
+                *attempts += 1;
+                *attempts
+            };
+            async move {
+                if attempt == 1 {
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                }
+                Ok(mock::json(REPLY))
+            }
+        })
+        .with_timeout(std::time::Duration::from_millis(50));
+        let result = text_translate(&client).build().unwrap().doit(|b| b).await;
+        assert!(matches!(result, Err(Error::Middleware(_))), "{result:?}");
+
+        *attempts.lock().unwrap() = 0;
+        let mut delegate = RetryingTimeouts;
+        let body = text_translate(&client)
+            .delegate(&mut delegate)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        assert_eq!(body, REPLY.as_bytes());
+        assert_eq!(*attempts.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn cancel_cuts_the_backoff_short() {
+        let client = unavailable();
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let mut delegate = Retrying(std::time::Duration::from_secs(30));
+        let started = Instant::now();
+        let result = text_translate(&client)
+            .delegate(&mut delegate)
+            .cancel(token)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn hedge_goes_to_the_regional_endpoint() {
+        let hosts = Arc::new(Mutex::new(Vec::new()));
+        let seen = hosts.clone();
+        let client = mock::client(move |req| {
+            let host = req.uri().host().unwrap_or_default().to_string();
+            let region = req.headers()["X-TC-Region"].to_str().unwrap().to_string();
+            seen.lock().unwrap().push((host.clone(), region));
+            async move {
+                // the nearest endpoint is slow
+                let ms = if host == BASE_HOST { 300 } else { 100 };
+                tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+                Ok(mock::json(REPLY))
+            }
+        })
+        .with_hedging(crate::api::HedgeConfig {
+            initial_delay: std::time::Duration::from_millis(20),
+            min_samples: 1,
+            region: Some("ap-shanghai".to_string()),
+            ..Default::default()
+        });
+        text_translate(&client)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        assert_eq!(
+            *hosts.lock().unwrap(),
+            [
+                (BASE_HOST.to_string(), "ap-guangzhou".to_string()),
+                (
+                    "tmt.ap-shanghai.tencentcloudapi.com".to_string(),
+                    "ap-shanghai".to_string()
+                ),
+            ]
+        );
+        // the hedge won after ~100ms, the primary took at least as long
+        let hedger = client.hedger.as_ref().unwrap();
+        let delay = hedger.delay();
+        assert!(delay >= std::time::Duration::from_millis(100), "{delay:?}");
+        assert!(delay < std::time::Duration::from_millis(300), "{delay:?}");
+    }
+
+    #[tokio::test]
+    async fn hedged_requests_report_to_the_delegate() {
+        let client = mock::client(|req| async move {
+            let primary = req.uri().host() == Some(BASE_HOST);
+            let ms = if primary { 5000 } else { 50 };
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            Ok(mock::json(REPLY))
+        })
+        .with_hedging(crate::api::HedgeConfig {
+            initial_delay: std::time::Duration::from_millis(20),
+            ..Default::default()
+        });
+        let mut delegate = Outcome::default();
+        let started = Instant::now();
+        text_translate(&client)
+            .delegate(&mut delegate)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        // the hedge succeeded, then the primary was cancelled
+        assert_eq!(delegate.post_responses, 1);
+        assert_eq!(delegate.finished, [true, false]);
+    }
+
+    struct Post(fn(&client::ResponseInfo<'_>) -> client::PostResponse, usize);
+
+    impl Delegate for Post {
+        fn post_response(&mut self, response: &client::ResponseInfo<'_>) -> client::PostResponse {
+            self.1 += 1;
+            assert_eq!(response.request_id, Some("1"));
+            assert!(response.status.is_success());
+            (self.0)(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn post_response_keeps_replaces_or_rejects() {
+        let (client, _) = mock::sequence(vec![REPLY]);
+        let run = |post: fn(&client::ResponseInfo<'_>) -> client::PostResponse| {
+            let client = &client;
+            async move {
+                let mut delegate = Post(post, 0);
+                let result = text_translate(client)
+                    .delegate(&mut delegate)
+                    .build()
+                    .unwrap()
+                    .doit(|b| b)
+                    .await;
+                assert_eq!(delegate.1, 1);
+                result
+            }
+        };
+        let kept = run(|_| client::PostResponse::Keep).await.unwrap();
+        assert_eq!(kept, REPLY.as_bytes());
+
+        let replaced = run(|response| {
+            let mut body = response.body.clone();
+            body["Response"]["TargetText"] = "你好".into();
+            client::PostResponse::Replace(body)
+        })
+        .await
+        .unwrap();
+        let replaced: TextTranslateResponse = decode_response(&replaced).unwrap();
+        assert_eq!(replaced.target_text, "你好");
+
+        let rejected = run(|_| client::PostResponse::Reject("stale".to_string())).await;
+        assert!(matches!(rejected, Err(Error::Rejected(reason)) if reason == "stale"));
+    }
+
+    #[tokio::test]
+    async fn post_response_skips_bodies_not_json() {
+        let (client, _) = mock::sequence(vec!["plain text"]);
+        let mut delegate = Post(|_| client::PostResponse::Reject("never".to_string()), 0);
+        let body = text_translate(&client)
+            .delegate(&mut delegate)
+            .build()
+            .unwrap()
+            .doit(|b| b)
+            .await
+            .unwrap();
+        assert_eq!(body, b"plain text");
+        assert_eq!(delegate.1, 0);
+    }
+
+    /// a body sent in `chunks`, without a size hint
+    fn chunked(chunks: Vec<Bytes>) -> Body {
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                tx.send_data(chunk).await.unwrap();
+            }
+        });
+        body
+    }
+
+    #[tokio::test]
+    async fn read_body_limits_and_errors() {
+        let result = read_body(Body::from(vec![b' '; 100]), Some(10)).await;
+        assert!(matches!(
+            result,
+            Err(Error::ResponseSizeLimitExceeded(100, 10))
+        ));
+
+        // no size hint, the limit is hit between chunks
+        let body = chunked(vec![Bytes::from(vec![b' '; 5]); 3]);
+        let result = read_body(body, Some(12)).await;
+        assert!(matches!(
+            result,
+            Err(Error::ResponseSizeLimitExceeded(15, 12))
+        ));
+
+        let (mut tx, body) = Body::channel();
+        tx.send_data(Bytes::from_static(b"{")).await.unwrap();
+        tx.abort();
+        assert!(matches!(
+            read_body(body, None).await,
+            Err(Error::HttpError(_))
+        ));
+    }
+
+    /// counts post_response and records how calls finished
+    #[derive(Default)]
+    struct Outcome {
+        post_responses: usize,
+        finished: Vec<bool>,
+    }
+
+    impl Delegate for Outcome {
+        fn post_response(&mut self, _response: &client::ResponseInfo<'_>) -> client::PostResponse {
+            self.post_responses += 1;
+            client::PostResponse::Keep
+        }
+
+        fn finished(&mut self, is_success: bool) {
+            self.finished.push(is_success);
+        }
+    }
+
+    async fn stream_file_data(
+        client: &mock::MockClient,
+        delegate: &mut Outcome,
+    ) -> Vec<Result<Bytes>> {
+        client
+            .translate()
+            .get_file_translate_data()
+            .task_id("t1")
+            .delegate(delegate)
+            .build()
+            .unwrap()
+            .doit_stream()
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn stream_sees_api_errors_and_hands_on_documents() {
+        const API_ERROR: &str =
+            r#"{"Response":{"RequestId":"1","Error":{"Code":"FailedOperation","Message":"no"}}}"#;
+        let (client, _) = mock::sequence(vec![API_ERROR]);
+        let mut delegate = Outcome::default();
+        let chunks = stream_file_data(&client, &mut delegate).await;
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_ref().unwrap(), API_ERROR.as_bytes());
+        assert_eq!(delegate.post_responses, 1);
+        assert_eq!(delegate.finished, [false]);
+
+        let document = format!(
+            r#"{{"Response":{{"RequestId":"2","Data":{{"FileData":"{}"}}}}}}"#,
+            "A".repeat(3 * STREAM_PEEK_SIZE)
+        );
+        let chunks = document
+            .as_bytes()
+            .chunks(1024)
+            .map(Bytes::copy_from_slice)
+            .collect::<Vec<_>>();
+        let client = mock::client(move |_| {
+            let chunks = chunks.clone();
+            async move { Ok(hyper::Response::new(chunked(chunks))) }
+        });
+        let mut delegate = Outcome::default();
+        let mut received = Vec::new();
+        for chunk in stream_file_data(&client, &mut delegate).await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(received, document.as_bytes());
+        assert_eq!(delegate.post_responses, 0);
+        assert_eq!(delegate.finished, [true]);
+    }
+
+    #[tokio::test]
+    async fn stream_yields_read_errors() {
+        let client = mock::client(|_| async {
+            let (mut tx, body) = Body::channel();
+            tokio::spawn(async move {
+                let head = vec![b' '; STREAM_PEEK_SIZE + 1];
+                tx.send_data(Bytes::from(head)).await.unwrap();
+                tx.abort();
+            });
+            Ok(hyper::Response::new(body))
+        });
+        let mut delegate = Outcome::default();
+        let chunks = stream_file_data(&client, &mut delegate).await;
+        assert_eq!(delegate.finished, [true]);
+        assert_eq!(chunks[0].as_ref().unwrap().len(), STREAM_PEEK_SIZE + 1);
+        assert!(matches!(chunks.last(), Some(Err(Error::HttpError(_)))));
+    }
+}
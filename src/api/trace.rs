@@ -0,0 +1,163 @@
+//! Spans opened by `doit`, compiled to nothing without the `tracing` feature.
+//!
+//! A `tencent3.call` span covers a whole call and an `attempt` span every
+//! http request in it. Credentials are never recorded, request and response
+//! bodies only when [`TencentClient::trace_bodies`](crate::TencentClient) is set.
+
+use std::{future::Future, time::Duration};
+
+#[cfg(feature = "tracing")]
+use tracing::{field::Empty, Instrument, Span};
+
+pub(crate) struct CallSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+    #[cfg(feature = "tracing")]
+    bodies: bool,
+}
+
+pub(crate) struct AttemptSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+}
+
+#[cfg(feature = "tracing")]
+impl CallSpan {
+    pub fn new(id: &str, action: &str, region: &str, payload: &str, bodies: bool) -> Self {
+        let span = tracing::info_span!(
+            "tencent3.call",
+            id,
+            action,
+            region,
+            attempts = Empty,
+            status = Empty,
+            request_id = Empty,
+            error_code = Empty,
+            latency_ms = Empty,
+            payload = Empty,
+            response = Empty,
+        );
+        if bodies {
+            span.record("payload", payload);
+        }
+        Self { span, bodies }
+    }
+
+    pub fn instrument<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        fut.instrument(self.span.clone())
+    }
+
+    pub fn attempt(&self, attempt: usize) -> AttemptSpan {
+        let span = tracing::debug_span!(
+            parent: &self.span,
+            "attempt",
+            attempt,
+            status = Empty,
+            latency_ms = Empty,
+            error = Empty,
+        );
+        AttemptSpan { span }
+    }
+
+    /// record the outcome, `body` is the response of a successful request
+    pub fn finish(
+        &self,
+        attempts: usize,
+        status: Option<u16>,
+        body: Option<&[u8]>,
+        latency: Duration,
+    ) {
+        let span = &self.span;
+        span.record("attempts", attempts);
+        span.record("latency_ms", latency.as_millis() as u64);
+        if let Some(status) = status {
+            span.record("status", status);
+        }
+        let Some(body) = body else {
+            return;
+        };
+        if self.bodies {
+            span.record("response", String::from_utf8_lossy(body).as_ref());
+        }
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(body) else {
+            return;
+        };
+        let response = &value["Response"];
+        if let Some(request_id) = response["RequestId"].as_str() {
+            span.record("request_id", request_id);
+        }
+        if let Some(code) = response["Error"]["Code"].as_str() {
+            span.record("error_code", code);
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl AttemptSpan {
+    pub fn instrument<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        fut.instrument(self.span.clone())
+    }
+
+    pub fn finish(
+        &self,
+        status: Option<u16>,
+        error: Option<&dyn std::fmt::Display>,
+        latency: Duration,
+    ) {
+        if let Some(status) = status {
+            self.span.record("status", status);
+        }
+        if let Some(error) = error {
+            self.span.record("error", tracing::field::display(error));
+        }
+        self.span.record("latency_ms", latency.as_millis() as u64);
+    }
+}
+
+/// report a streamed body failing to be read, after its call span closed
+#[cfg(feature = "tracing")]
+pub(crate) fn stream_error(action: &str, error: &hyper::Error) {
+    tracing::warn!(action, %error, "tencent3 response stream failed");
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn stream_error(_action: &str, _error: &hyper::Error) {}
+
+#[cfg(not(feature = "tracing"))]
+impl CallSpan {
+    pub fn new(_id: &str, _action: &str, _region: &str, _payload: &str, _bodies: bool) -> Self {
+        Self {}
+    }
+
+    pub fn instrument<F: Future>(&self, fut: F) -> F {
+        fut
+    }
+
+    pub fn attempt(&self, _attempt: usize) -> AttemptSpan {
+        AttemptSpan {}
+    }
+
+    pub fn finish(
+        &self,
+        _attempts: usize,
+        _status: Option<u16>,
+        _body: Option<&[u8]>,
+        _latency: Duration,
+    ) {
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl AttemptSpan {
+    pub fn instrument<F: Future>(&self, fut: F) -> F {
+        fut
+    }
+
+    pub fn finish(
+        &self,
+        _status: Option<u16>,
+        _error: Option<&dyn std::fmt::Display>,
+        _latency: Duration,
+    ) {
+    }
+}
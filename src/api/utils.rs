@@ -1,6 +1,11 @@
+use std::future::Future;
+
 use chrono::TimeZone;
 use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
+use tokio_util::sync::CancellationToken;
+
+use crate::Error;
 
 const HMAC_ALGORITHM: &str = "TC3-HMAC-SHA256";
 
@@ -15,7 +20,7 @@ pub struct SignatureV3Arg<'a> {
 }
 
 // 生成v3签名
-pub fn signature_v3_with_post(arg: SignatureV3Arg) -> String {
+pub fn signature_v3_with_post(arg: SignatureV3Arg) -> crate::Result<String> {
     use chrono::Utc;
     // build canonical request string
     let hashed_payload = sha256_hex(arg.request_payload);
@@ -29,7 +34,12 @@ pub fn signature_v3_with_post(arg: SignatureV3Arg) -> String {
     let datetime = if arg.timestamp == 0 {
         Utc::now()
     } else {
-        Utc.timestamp_opt(arg.timestamp as i64, 0).unwrap()
+        i64::try_from(arg.timestamp)
+            .ok()
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!("timestamp {} is out of range", arg.timestamp))
+            })?
     };
     let date = datetime.format("%F").to_string();
     let canonical_scope = format!("{}/{}/tc3_request", date, arg.service);
@@ -43,27 +53,28 @@ pub fn signature_v3_with_post(arg: SignatureV3Arg) -> String {
     );
 
     // sign string
-    let secret_date = hmac_sha256(&date, format!("TC3{}", arg.secret_key));
-    let secret_service = hmac_sha256(arg.service, secret_date);
-    let secret_key = hmac_sha256("tc3_request", secret_service);
-    let signature = to_hex_string(hmac_sha256(sign_string, secret_key).as_slice());
+    let secret_date = hmac_sha256(&date, format!("TC3{}", arg.secret_key))?;
+    let secret_service = hmac_sha256(arg.service, secret_date)?;
+    let secret_key = hmac_sha256("tc3_request", secret_service)?;
+    let signature = to_hex_string(hmac_sha256(sign_string, secret_key)?.as_slice());
 
-    format!(
+    Ok(format!(
         "{HMAC_ALGORITHM} Credential={}/{}, SignedHeaders={}, Signature={signature}",
         arg.secret_id, canonical_scope, signed_header
-    )
+    ))
 }
 
-fn hmac_sha256<S, K>(payload: S, key: K) -> Vec<u8>
+fn hmac_sha256<S, K>(payload: S, key: K) -> crate::Result<Vec<u8>>
 where
     S: AsRef<[u8]>,
     K: AsRef<[u8]>,
 {
     let payload = payload.as_ref();
     let key = key.as_ref();
-    let mut hmac: Hmac<Sha256> = Hmac::new_from_slice(key).expect("invalid key");
+    let mut hmac: Hmac<Sha256> = Hmac::new_from_slice(key)
+        .map_err(|e| Error::InvalidArgument(format!("invalid signing key: {e}")))?;
     hmac.update(payload);
-    hmac.finalize().into_bytes().as_slice().to_vec()
+    Ok(hmac.finalize().into_bytes().as_slice().to_vec())
 }
 
 fn sha256_hex(payload: impl AsRef<[u8]>) -> String {
@@ -87,6 +98,31 @@ pub fn to_base64<S: AsRef<[u8]>>(bytes: S) -> String {
     general_purpose::STANDARD_NO_PAD.encode(bytes.as_ref())
 }
 
+pub fn from_base64<S: AsRef<[u8]>>(encoded: S) -> Result<Vec<u8>, base64::DecodeError> {
+    use base64::{
+        alphabet,
+        engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+        Engine as _,
+    };
+    // padding is optional, we upload without it and the api answers with it
+    const ENGINE: GeneralPurpose = GeneralPurpose::new(
+        &alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    ENGINE.decode(encoded.as_ref())
+}
+
+/// run `f` to completion, or fail with [`Error::Cancelled`] once `token` is
+pub(crate) async fn unless_cancelled<F: Future>(
+    token: Option<&CancellationToken>,
+    f: F,
+) -> crate::Result<F::Output> {
+    match token {
+        Some(token) => token.run_until_cancelled(f).await.ok_or(Error::Cancelled),
+        None => Ok(f.await),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,4 +135,32 @@ mod tests {
             "35e9c5b0e3ae67532d3c9f17ead6c90222632e5b1ff7f6e89887f1398934f064"
         );
     }
+
+    #[test]
+    fn signature_rejects_bad_timestamp() {
+        let arg = |timestamp| SignatureV3Arg {
+            content_type: "application/json",
+            host: "tmt.tencentcloudapi.com",
+            request_payload: "{}",
+            service: "tmt",
+            secret_key: "key",
+            secret_id: "id",
+            timestamp,
+        };
+        assert!(signature_v3_with_post(arg(1_551_113_065)).is_ok());
+        assert!(matches!(
+            signature_v3_with_post(arg(u64::MAX)),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(to_hex_string(&[0x00, 0xab, 0x7f]), "00ab7f");
+    }
+
+    #[test]
+    fn base64_padding_is_optional() {
+        assert_eq!(from_base64("5L2g5aW9").unwrap(), "你好".as_bytes());
+        assert_eq!(from_base64("aGk=").unwrap(), b"hi");
+        assert_eq!(from_base64("aGk").unwrap(), b"hi");
+        assert_eq!(from_base64(to_base64(b"\x00\xff")).unwrap(), b"\x00\xff");
+        assert!(from_base64("a?b").is_err());
+    }
 }
@@ -1,15 +1,46 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
-use hyper::Request;
-use hyper::{client::HttpConnector, Body, Client, Method, Response};
+use hyper::{client::connect::Connection, service::Service, Request, Uri};
+use hyper::{client::HttpConnector, Body, Client, HeaderMap, Method, Response, StatusCode};
 use hyper_rustls::HttpsConnector;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::{Layer, ServiceBuilder};
 
-use crate::api::TranslateMethods;
+use crate::api::{
+    breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitKey, CircuitState},
+    hedge::{HedgeConfig, Hedger},
+    middleware::{
+        ApiRequest, ApiResponse, ApiService, BoxError, CacheLayer, RateLimitLayer, SignLayer,
+        Transport,
+    },
+    TranslateMethods,
+};
+use crate::hooks::Hooks;
+
+type WrapFn = Arc<dyn Fn(ApiService) -> ApiService + Send + Sync>;
 
 pub struct TencentClient<S> {
     pub client: Client<S>,
     pub credential: Credential,
     pub user_agent: String,
+    /// record request and response bodies in `tracing` spans, off by
+    /// default as they may hold user content
+    pub trace_bodies: bool,
+    /// applied in order, each wrapping the stack built so far
+    layers: Vec<WrapFn>,
+    /// built on first use, shared by all calls
+    service: OnceLock<ApiService>,
+    /// off unless set with `with_circuit_breaker`
+    pub(crate) breaker: Option<CircuitBreaker>,
+    /// used by calls without a delegate
+    pub(crate) hooks: Option<Arc<dyn Hooks>>,
+    /// off unless set with `with_hedging`
+    pub(crate) hedger: Option<Hedger>,
+    /// unlimited unless set with `with_max_response_size`
+    pub(crate) max_response_size: Option<usize>,
 }
 
 pub struct Credential {
@@ -23,14 +54,107 @@ impl<'a, S> TencentClient<S> {
             client,
             credential,
             user_agent: r#"Mozilla/5.0 Safari/537.36"#.to_string(),
+            trace_bodies: false,
+            layers: Vec::new(),
+            service: OnceLock::new(),
+            breaker: None,
+            hooks: None,
+            hedger: None,
+            max_response_size: None,
         }
     }
+
+    /// Wrap the request pipeline in `layer`. Layers added later run first,
+    /// all of them before signing, see [`middleware`](crate::api::middleware).
+    pub fn with_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<ApiService> + Send + Sync + 'static,
+        L::Service: Service<ApiRequest, Response = ApiResponse, Error = BoxError>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        <L::Service as Service<ApiRequest>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Arc::new(move |inner| ApiService::new(layer.layer(inner))));
+        self.service = OnceLock::new();
+        self
+    }
+
+    /// fail attempts taking longer than `timeout`
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_layer(tower::timeout::TimeoutLayer::new(timeout))
+    }
+
+    /// start at most `num` requests every `per`
+    pub fn with_rate_limit(self, num: u32, per: Duration) -> Self {
+        self.with_layer(RateLimitLayer::new(num, per))
+    }
+
+    /// cache successful text translations and detections for `ttl`
+    pub fn with_cache(self, ttl: Duration, capacity: usize) -> Self {
+        self.with_layer(CacheLayer::new(ttl, capacity))
+    }
+
+    /// fail calls whose response body is larger than `bytes`, streamed
+    /// bodies are not limited
+    pub fn with_max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// fail calls fast while their service, region and action keeps failing,
+    /// see [`CircuitBreakerConfig`]
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.breaker = Some(CircuitBreaker::new(config));
+        self
+    }
+
+    /// Send a second, identical request for text translations taking longer
+    /// than usual, see [`HedgeConfig`]. The first success wins and the other
+    /// request is cancelled. Both report to the delegate or hooks of the call,
+    /// each from `begin` to `finished`.
+    pub fn with_hedging(mut self, config: HedgeConfig) -> Self {
+        self.hedger = Some(Hedger::new(config));
+        self
+    }
+
+    /// run `hooks` around every call not given a delegate, wrap a
+    /// [`Delegate`] in [`DelegateHooks`](crate::hooks::DelegateHooks) to share it
+    pub fn with_hooks(mut self, hooks: Arc<dyn Hooks>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
     /// Tencent Machine Translate APIs
     pub fn translate(&'a self) -> TranslateMethods<'a, S> {
         TranslateMethods { client: self }
     }
 }
 
+impl<S> TencentClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// the request pipeline: added layers, signing and the hyper client
+    pub(crate) fn service(&self) -> ApiService {
+        self.service
+            .get_or_init(|| {
+                let base = ServiceBuilder::new()
+                    .layer(SignLayer::new(&self.credential.id, &self.credential.key))
+                    .service(Transport::new(self.client.clone()));
+                self.layers
+                    .iter()
+                    .fold(ApiService::new(base), |inner, wrap| wrap(inner))
+            })
+            .clone()
+    }
+}
+
 impl TencentClient<HttpsConnector<HttpConnector>> {
     /// construct HyperClient with no proxy
     pub fn native(credential: Credential) -> Self {
@@ -78,11 +202,32 @@ pub trait Delegate: Send {
         Retry::Abort
     }
 
+    /// Called whenever a layer of the pipeline fails a request, e.g. with the
+    /// `Elapsed` error of [`TencentClient::with_timeout`]. Retries like
+    /// [`http_error`](Self::http_error).
+    fn middleware_error(
+        &mut self,
+        _err: &(dyn std::error::Error + Send + Sync + 'static),
+    ) -> Retry {
+        Retry::Abort
+    }
+
     /// Called prior to sending the main request of the given method. It can be used to time
     /// the call or to print progress information.
     /// It's also useful as you can be sure that a request will definitely be made.
+    /// The request is not signed yet, `Authorization` and `X-TC-Timestamp` are
+    /// added at the end of the request pipeline.
     fn pre_request(&mut self, _request: &Request<Body>) {}
 
+    /// Called with every successful response carrying a json body, before it is
+    /// decoded for the caller. It can be used to capture the `RequestId` or
+    /// headers, or to rewrite or reject the response. A body that is not json
+    /// is handed on without calling it, as is a streamed body longer than an
+    /// api error can be.
+    fn post_response(&mut self, _response: &ResponseInfo<'_>) -> PostResponse {
+        PostResponse::Keep
+    }
+
     /// retry times when http failure
     fn retry_times(&self) -> u8 {
         3
@@ -98,6 +243,11 @@ pub trait Delegate: Send {
     fn finished(&mut self, is_success: bool) {
         let _ = is_success;
     }
+
+    /// Called when the circuit breaker of the client moves the circuit `key`
+    /// from one state to another, e.g. it opens after sustained failures.
+    fn circuit_state_changed(&mut self, _key: &CircuitKey, _from: CircuitState, _to: CircuitState) {
+    }
 }
 
 /// Contains information about an API request.
@@ -113,6 +263,62 @@ pub struct DefaultDelegate;
 
 impl Delegate for DefaultDelegate {}
 
+impl<D: Delegate + ?Sized> Delegate for &mut D {
+    fn begin(&mut self, info: MethodInfo) {
+        (**self).begin(info)
+    }
+
+    fn http_failure(&mut self, response: &Response<Body>) -> Retry {
+        (**self).http_failure(response)
+    }
+
+    fn http_error(&mut self, err: &hyper::Error) -> Retry {
+        (**self).http_error(err)
+    }
+
+    fn middleware_error(&mut self, err: &(dyn std::error::Error + Send + Sync + 'static)) -> Retry {
+        (**self).middleware_error(err)
+    }
+
+    fn pre_request(&mut self, request: &Request<Body>) {
+        (**self).pre_request(request)
+    }
+
+    fn post_response(&mut self, response: &ResponseInfo<'_>) -> PostResponse {
+        (**self).post_response(response)
+    }
+
+    fn retry_times(&self) -> u8 {
+        (**self).retry_times()
+    }
+
+    fn finished(&mut self, is_success: bool) {
+        (**self).finished(is_success)
+    }
+
+    fn circuit_state_changed(&mut self, key: &CircuitKey, from: CircuitState, to: CircuitState) {
+        (**self).circuit_state_changed(key, from, to)
+    }
+}
+
+/// A successful response, as seen by [`Delegate::post_response`]
+pub struct ResponseInfo<'a> {
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+    /// `Response.RequestId` of the body
+    pub request_id: Option<&'a str>,
+    pub body: &'a serde_json::Value,
+}
+
+pub enum PostResponse {
+    /// Hand the response on unchanged
+    Keep,
+    /// Hand on the given body instead
+    Replace(serde_json::Value),
+    /// Fail the call with [`Error::Rejected`](crate::Error::Rejected)
+    Reject(String),
+}
+
 pub enum Retry {
     /// Signal you don't want to retry
     Abort,
@@ -0,0 +1,326 @@
+//! Async hooks around every api call.
+//!
+//! Unlike [`Delegate`] they take `&self`, may await and are shared by all
+//! calls of a client through [`TencentClient::with_hooks`], or set on a
+//! single call builder. A delegate set on a call builder takes precedence
+//! over both, wrapped in [`DelegateHooks`].
+//!
+//! [`TencentClient::with_hooks`]: crate::TencentClient::with_hooks
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use hyper::{Body, Method, Request, Response};
+
+use crate::{
+    api::{CircuitKey, CircuitState},
+    client::{Delegate, MethodInfo, PostResponse, ResponseInfo, Retry},
+};
+
+/// What is known about a call when a hook runs
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    /// method id, e.g. `tmt.TextTranslate`
+    pub id: &'static str,
+    pub action: &'static str,
+    /// empty for actions without a region
+    pub region: String,
+    /// 1 for the first request, 0 before it
+    pub attempt: usize,
+    /// `RequestId` of the last response carrying one, known from
+    /// `post_response` on
+    pub request_id: Option<String>,
+    /// `Response.Error` of the last response carrying one
+    pub api_error: Option<ApiError>,
+}
+
+/// Error returned by Tencent Cloud within a successful http response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+}
+
+impl CallContext {
+    pub(crate) fn new(id: &'static str, action: &'static str, region: String) -> Self {
+        Self {
+            id,
+            action,
+            region,
+            attempt: 0,
+            request_id: None,
+            api_error: None,
+        }
+    }
+
+    /// pick up the request id and error of a response body
+    pub(crate) fn read_response(&mut self, value: &serde_json::Value) {
+        let response = &value["Response"];
+        self.request_id = response["RequestId"].as_str().map(str::to_string);
+        self.api_error = response["Error"]["Code"].as_str().map(|code| ApiError {
+            code: code.to_string(),
+            message: response["Error"]["Message"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+}
+
+/// Async counterpart of [`Delegate`], every method has a no-op default.
+#[async_trait]
+pub trait Hooks: Send + Sync {
+    /// Called once at the beginning of a call.
+    async fn begin(&self, _cx: &CallContext) {}
+
+    /// Called before every request of a call, retries included, with the
+    /// request before signing, see [`Delegate::pre_request`].
+    async fn pre_request(&self, _cx: &CallContext, _request: &Request<Body>) {}
+
+    /// Called with every successful response carrying a json body, see
+    /// [`Delegate::post_response`].
+    async fn post_response(&self, _cx: &CallContext, _response: &ResponseInfo<'_>) -> PostResponse {
+        PostResponse::Keep
+    }
+
+    /// Called when a request returns with a non-success status code.
+    async fn http_failure(&self, _cx: &CallContext, _response: &Response<Body>) -> Retry {
+        Retry::Abort
+    }
+
+    /// Called when a request fails with an [HttpError](hyper::Error).
+    async fn http_error(&self, _cx: &CallContext, _err: &hyper::Error) -> Retry {
+        Retry::Abort
+    }
+
+    /// Called when a layer fails a request, see [`Delegate::middleware_error`].
+    async fn middleware_error(
+        &self,
+        _cx: &CallContext,
+        _err: &(dyn std::error::Error + Send + Sync + 'static),
+    ) -> Retry {
+        Retry::Abort
+    }
+
+    /// requests made at most per call
+    fn retry_times(&self) -> u8 {
+        3
+    }
+
+    /// Called when the circuit breaker moves the circuit of the call.
+    async fn circuit_state_changed(
+        &self,
+        _cx: &CallContext,
+        _key: &CircuitKey,
+        _from: CircuitState,
+        _to: CircuitState,
+    ) {
+    }
+
+    /// Called once before the call returns, in every case. `is_success` is
+    /// false for api errors too, see [`CallContext::api_error`].
+    async fn finished(&self, _cx: &CallContext, _is_success: bool) {}
+}
+
+/// Run a synchronous [`Delegate`] as [`Hooks`]
+pub struct DelegateHooks<D>(Mutex<D>);
+
+impl<D: Delegate> DelegateHooks<D> {
+    pub fn new(delegate: D) -> Self {
+        Self(Mutex::new(delegate))
+    }
+
+    pub fn into_inner(self) -> D {
+        self.0.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut D) -> T) -> T {
+        f(&mut self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[async_trait]
+impl<D: Delegate> Hooks for DelegateHooks<D> {
+    async fn begin(&self, cx: &CallContext) {
+        self.with(|d| {
+            d.begin(MethodInfo {
+                id: cx.id,
+                http_method: Method::POST,
+            })
+        })
+    }
+
+    async fn pre_request(&self, _cx: &CallContext, request: &Request<Body>) {
+        self.with(|d| d.pre_request(request))
+    }
+
+    async fn post_response(&self, _cx: &CallContext, response: &ResponseInfo<'_>) -> PostResponse {
+        self.with(|d| d.post_response(response))
+    }
+
+    async fn http_failure(&self, _cx: &CallContext, response: &Response<Body>) -> Retry {
+        self.with(|d| d.http_failure(response))
+    }
+
+    async fn http_error(&self, _cx: &CallContext, err: &hyper::Error) -> Retry {
+        self.with(|d| d.http_error(err))
+    }
+
+    async fn middleware_error(
+        &self,
+        _cx: &CallContext,
+        err: &(dyn std::error::Error + Send + Sync + 'static),
+    ) -> Retry {
+        self.with(|d| d.middleware_error(err))
+    }
+
+    fn retry_times(&self) -> u8 {
+        self.with(|d| d.retry_times())
+    }
+
+    async fn circuit_state_changed(
+        &self,
+        _cx: &CallContext,
+        key: &CircuitKey,
+        from: CircuitState,
+        to: CircuitState,
+    ) {
+        self.with(|d| d.circuit_state_changed(key, from, to))
+    }
+
+    async fn finished(&self, _cx: &CallContext, is_success: bool) {
+        self.with(|d| d.finished(is_success))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{api::mock, Language};
+
+    const REPLY: &str =
+        r#"{"Response":{"RequestId":"r1","Source":"en","Target":"zh","TargetText":"嗨"}}"#;
+    const API_ERROR: &str =
+        r#"{"Response":{"RequestId":"r2","Error":{"Code":"FailedOperation","Message":"no"}}}"#;
+
+    async fn translate(
+        client: &mock::MockClient,
+        hooks: Option<&dyn Hooks>,
+    ) -> crate::Result<Vec<u8>> {
+        let mut builder = client
+            .translate()
+            .text_translate()
+            .project_id(0u32)
+            .source(Language::En)
+            .target(Language::Zh)
+            .region("ap-guangzhou")
+            .source_text("hi");
+        if let Some(hooks) = hooks {
+            builder = builder.hooks(hooks);
+        }
+        builder.build().unwrap().doit(|b| b).await
+    }
+
+    /// stages seen, with the request id and error code known at the time
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn push(&self, stage: &str, cx: &CallContext) {
+            let code = cx.api_error.as_ref().map(|e| e.code.as_str());
+            self.0.lock().unwrap().push(format!(
+                "{stage} {} {}",
+                cx.request_id.as_deref().unwrap_or("-"),
+                code.unwrap_or("-")
+            ));
+        }
+    }
+
+    #[async_trait]
+    impl Hooks for Recorder {
+        async fn begin(&self, cx: &CallContext) {
+            self.push("begin", cx);
+        }
+
+        async fn pre_request(&self, cx: &CallContext, _request: &Request<Body>) {
+            self.push("pre_request", cx);
+        }
+
+        async fn post_response(
+            &self,
+            cx: &CallContext,
+            _response: &ResponseInfo<'_>,
+        ) -> PostResponse {
+            self.push("post_response", cx);
+            PostResponse::Keep
+        }
+
+        async fn finished(&self, cx: &CallContext, is_success: bool) {
+            self.push(&format!("finished({is_success})"), cx);
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_see_request_id_and_api_error() {
+        let (client, _) = mock::sequence(vec![REPLY, API_ERROR]);
+        let recorder = Arc::new(Recorder::default());
+        let client = client.with_hooks(recorder.clone());
+        translate(&client, None).await.unwrap();
+        translate(&client, None).await.unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "begin - -",
+                "pre_request - -",
+                "post_response r1 -",
+                "finished(true) r1 -",
+                "begin - -",
+                "pre_request - -",
+                "post_response r2 FailedOperation",
+                "finished(false) r2 FailedOperation",
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        begun: usize,
+        requests: usize,
+        finished: Vec<bool>,
+    }
+
+    impl Delegate for Counter {
+        fn begin(&mut self, _info: MethodInfo) {
+            self.begun += 1;
+        }
+
+        fn pre_request(&mut self, _request: &Request<Body>) {
+            self.requests += 1;
+        }
+
+        fn finished(&mut self, is_success: bool) {
+            self.finished.push(is_success);
+        }
+    }
+
+    #[tokio::test]
+    async fn delegate_hooks_forward_to_the_delegate() {
+        let (client, _) = mock::sequence(vec![REPLY, API_ERROR]);
+        let hooks = DelegateHooks::new(Counter::default());
+        translate(&client, Some(&hooks)).await.unwrap();
+        translate(&client, Some(&hooks)).await.unwrap();
+        let counter = hooks.into_inner();
+        assert_eq!((counter.begun, counter.requests), (2, 2));
+        assert_eq!(counter.finished, [true, false]);
+
+        // a borrowed delegate works the same and stays with its owner
+        let mut counter = Counter::default();
+        {
+            let hooks = DelegateHooks::new(&mut counter);
+            translate(&client, Some(&hooks)).await.unwrap();
+        }
+        assert_eq!(counter.finished, [false]);
+    }
+}
@@ -16,8 +16,8 @@
 //!     let call = client
 //!         .translate()
 //!         .text_translate()
-//!         .source("it") // Italy
-//!         .target("zh")
+//!         .source(Language::It) // Italy
+//!         .target(Language::Zh)
 //!         .project_id(PROJECT_ID)
 //!         .region("REGION")
 //!         .source_text("Credere è destino")
@@ -57,21 +57,27 @@
 
 pub mod api;
 pub mod client;
-pub use api::CallOutput;
+pub mod hooks;
+pub use api::{CallOutput, Language};
 pub use client::{Credential, TencentClient};
 
 pub use hyper;
 pub use hyper_rustls;
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 pub enum Error {
     /// The http connection failed
     HttpError(hyper::Error),
 
-    /// An attempt was made to upload a resource with size stored in field `.0`
-    /// even though the maximum upload size is what is stored in field `.1`.
+    /// An attempt was made to upload a resource with size of at least field
+    /// `.0` even though the maximum upload size is what is stored in field `.1`.
     UploadSizeLimitExceeded(u64, u64),
 
+    /// A response with size of at least field `.0` was received even though
+    /// the maximum response size is what is stored in field `.1`.
+    ResponseSizeLimitExceeded(u64, u64),
+
     /// Represents information about a request that was not understood by the server.
     /// Details are included.
     BadRequest(serde_json::Value),
@@ -83,21 +89,50 @@ pub enum Error {
     /// We required a Token, but didn't get one from the Authenticator
     //MissingToken(oauth2::Error),
 
-    /// The delegate instructed to cancel the operation
+    /// The delegate instructed to cancel the operation, or its cancellation
+    /// token was cancelled
     Cancelled,
 
+    /// The delegate rejected a successful response, for the reason in field `.0`
+    Rejected(String),
+
     /// An additional, free form field clashed with one of the built-in optional ones
     FieldClash(&'static str),
 
     /// Missing field in CallBuilder
     MissingField(&'static str),
 
+    /// A CallBuilder was given an argument the api does not accept
+    InvalidArgument(String),
+
+    /// A request could not be built, e.g. a region or credential holds
+    /// characters not allowed in a header
+    InvalidHeader(hyper::http::Error),
+
     /// Shows that we failed to encode/decode request/response.
     /// This can happen if the protocol changes in conjunction with strict json decoding.
     JsonError(String, serde_json::Error),
 
-    /// Indicates an HTTP response with a non-success status code
-    Failure(hyper::Response<hyper::body::Body>),
+    /// A file translate task, identified by field `.0`, failed with the message in `.1`
+    TaskFailed(String, String),
+
+    /// A file translate task, identified by field `.0`, did not finish in time
+    TaskTimeout(String),
+
+    /// The translation of the text in field `.0` dropped some of its protected
+    /// markup, putting it back would corrupt the document
+    PlaceholdersLost(String),
+
+    /// A layer of the request pipeline failed the request, e.g. a timeout
+    Middleware(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The circuit of the call, identified by field `.0`, is open after
+    /// sustained failures and the call was not attempted
+    CircuitOpen(String),
+
+    /// Indicates an HTTP response with a non-success status code, boxed to keep
+    /// `Result` small
+    Failure(Box<hyper::Response<hyper::body::Body>>),
 
     /// An IO error occurred while reading a stream into memory
     Io(std::io::Error),
@@ -113,6 +148,11 @@ impl std::fmt::Display for Error {
                 "The media size {} exceeds the maximum allowed upload size of {}",
                 resource_size, max_size
             ),
+            Error::ResponseSizeLimitExceeded(ref response_size, ref max_size) => writeln!(
+                f,
+                "The response size {} exceeds the maximum allowed response size of {}",
+                response_size, max_size
+            ),
             Error::MissingAPIKey => {
                 (writeln!(
                     f,
@@ -129,6 +169,7 @@ impl std::fmt::Display for Error {
                 Ok(())
             }
             Error::Cancelled => writeln!(f, "Operation cancelled by delegate"),
+            Error::Rejected(ref reason) => writeln!(f, "Response rejected by delegate: {}", reason),
             Error::FieldClash(field) => writeln!(
                 f,
                 "The custom parameter '{}' is already provided natively by the CallBuilder.",
@@ -139,7 +180,24 @@ impl std::fmt::Display for Error {
                 "The parameter '{}' is missing by the CallBuilder.",
                 field
             ),
+            Error::InvalidArgument(ref message) => {
+                writeln!(f, "Invalid argument: {}", message)
+            }
+            Error::InvalidHeader(ref err) => writeln!(f, "Invalid header: {}", err),
             Error::JsonError(ref json_str, ref err) => writeln!(f, "{}: {}", err, json_str),
+            Error::TaskFailed(ref task_id, ref message) => {
+                writeln!(f, "Task {} failed: {}", task_id, message)
+            }
+            Error::TaskTimeout(ref task_id) => {
+                writeln!(f, "Task {} did not finish in time", task_id)
+            }
+            Error::PlaceholdersLost(ref text) => {
+                writeln!(f, "Protected markup lost in translation of: {}", text)
+            }
+            Error::Middleware(ref err) => writeln!(f, "Middleware error: {}", err),
+            Error::CircuitOpen(ref circuit) => {
+                writeln!(f, "Circuit {} is open, failing fast", circuit)
+            }
             Error::Failure(ref response) => {
                 writeln!(f, "Http status indicates failure: {:?}", response)
             }
@@ -152,6 +210,8 @@ impl std::error::Error for Error {
         match *self {
             Error::HttpError(ref err) => err.source(),
             Error::JsonError(_, ref err) => err.source(),
+            Error::Middleware(ref err) => Some(err.as_ref()),
+            Error::InvalidHeader(ref err) => Some(err),
             _ => None,
         }
     }